@@ -1,4 +1,6 @@
-use ipc::pipe::Fifo;
+use ipc::flags::{Mode, OpenFlags};
+use ipc::pipe::{Access, Fifo};
+use ipc::process::Fork;
 use ipc::Result;
 use std::env;
 use std::io::{Read, Write};
@@ -17,28 +19,31 @@ fn main() -> Result<()> {
 
     let path = "./fifo_test";
     let _ = std::fs::remove_file(path);
-    let mut fifo = Fifo::new(path, ipc::flags::O_RDWR, 0o666)?;
+    let mut fifo = Fifo::create(
+        path,
+        Access::ReadWrite,
+        OpenFlags::empty(),
+        Mode::from_bits_truncate(0o666),
+    )?;
 
     let mut buf = Vec::with_capacity(size as _);
     buf.resize(buf.capacity(), 0);
 
     match ipc::fork()? {
-        0 => {
+        Fork::Child => {
             let mut sum: isize = 0;
             for _i in 0..count {
-                sum += fifo.read(&mut buf)? as isize;
+                fifo.read_exact(&mut buf)?;
+                sum += buf.len() as isize;
             }
             if sum != count * size {
                 eprintln!("sum error: {} != {}", sum, count * size);
             }
         }
-        pid => {
+        Fork::Parent(mut child) => {
             let start = Instant::now();
             for _ in 0..count {
-                if fifo.write(&buf)? != buf.len() {
-                    eprintln!("write error");
-                    process::exit(1);
-                }
+                fifo.write_all(&buf)?;
             }
             let duration = start.elapsed();
             let sec = duration.as_micros() as f64 / 1000000f64;
@@ -48,7 +53,7 @@ fn main() -> Result<()> {
                 count as f64 / sec
             );
 
-            ipc::waitpid(pid, 0)?;
+            child.wait()?;
         }
     }
 
@@ -0,0 +1,131 @@
+use cfg_if::cfg_if;
+use ipc::Result;
+
+cfg_if! {
+    if #[cfg(not(target_os = "android"))] {
+        use ipc::flags::PipeFlags;
+        use ipc::heap::SharedHeap;
+        use ipc::pipe::{self, PipeReader, PipeWriter};
+        use ipc::process;
+        use std::io::{Read, Write};
+        use std::os::unix::io::AsRawFd;
+        use std::time::Instant;
+        use std::{env, mem};
+
+        const HEAP_SIZE: usize = 64 * 1024 * 1024;
+        const MAX_LEN: usize = 4096;
+        // 长度 1..=MAX_LEN 落在 32..=8192 字节的 9 个块大小里
+        const CLASSES: usize = 9;
+        const MAX_BLOCK: usize = 8192;
+
+        // 每轮：分配一块写上记号，clone 一份把偏移传给下一个进程，再接收上一个进程的块，核对后放掉
+        fn worker(
+            heap: &SharedHeap,
+            id: usize,
+            workers: usize,
+            rounds: usize,
+            mut next: PipeWriter,
+            mut prev: PipeReader,
+        ) -> Result<usize> {
+            // 同一时刻每个进程最多领先别人 workers 轮，存活的块有上限，不随轮数增长
+            let bound = workers * (workers + 2) * CLASSES * MAX_BLOCK;
+            let mut peak = 0;
+            let mut seed = id as u64 * 0x9e37_79b9_7f4a_7c15 + 1;
+            for round in 0..rounds {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                let len = (seed % MAX_LEN as u64) as usize + 1;
+
+                let mut block = heap.alloc(len)?;
+                let mark = (id * rounds + round) as u8;
+                block.get_mut().unwrap().fill(mark);
+                let shared = block.clone();
+                next.write_all(&shared.into_offset().to_ne_bytes())?;
+                drop(block);
+
+                let mut raw = [0u8; mem::size_of::<u64>()];
+                prev.read_exact(&mut raw)?;
+                let block = unsafe { heap.from_offset(u64::from_ne_bytes(raw))? };
+                let from = (id + workers - 1) % workers;
+                let expected = (from * rounds + round) as u8;
+                if block.as_slice().iter().any(|&b| b != expected) {
+                    panic!("worker {}: corrupted block in round {}", id, round);
+                }
+                drop(block);
+
+                peak = peak.max(heap.used());
+                if peak > bound {
+                    panic!("worker {}: heap used {} bytes, over {}", id, peak, bound);
+                }
+            }
+            Ok(peak)
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    cfg_if! {
+        if #[cfg(not(target_os = "android"))] {
+            let args = env::args().collect::<Vec<_>>();
+            if args.len() < 3 {
+                eprintln!("wrong argument count (< 3)");
+                std::process::exit(1);
+            }
+
+            let workers: usize = args[1].parse()?;
+            let rounds: usize = args[2].parse()?;
+            if workers == 0 {
+                eprintln!("need at least one worker");
+                std::process::exit(1);
+            }
+
+            let heap = SharedHeap::anonymous(HEAP_SIZE)?;
+            let mut links = Vec::with_capacity(workers);
+            for _ in 0..workers {
+                links.push(pipe::pipe2(PipeFlags::CLOEXEC)?);
+            }
+            // 进程 i 写 links[i]，读 links[i - 1]
+            let (mut readers, mut writers): (Vec<_>, Vec<_>) =
+                links.into_iter().map(|(r, w)| (Some(r), Some(w))).unzip();
+
+            let start = Instant::now();
+            let mut handles = Vec::with_capacity(workers);
+            for id in 0..workers {
+                let writer = writers[id].take().unwrap();
+                let reader = readers[(id + workers - 1) % workers].take().unwrap();
+                // 子进程关掉别人的管道端：哪个进程死了，邻居读到 EOF 而不是一直等下去
+                let others = writers
+                    .iter()
+                    .flatten()
+                    .map(AsRawFd::as_raw_fd)
+                    .chain(readers.iter().flatten().map(AsRawFd::as_raw_fd))
+                    .collect::<Vec<_>>();
+                let heap = &heap;
+                handles.push(process::run_in_child(move || {
+                    for fd in others {
+                        unsafe { libc::close(fd) };
+                    }
+                    worker(heap, id, workers, rounds, writer, reader).unwrap()
+                })?);
+            }
+            // 工作进程超限或读到坏块会 panic，join 时报出来
+            let mut peak = 0;
+            for handle in handles {
+                peak = peak.max(handle.join()?);
+            }
+            let duration = start.elapsed();
+
+            let sec = duration.as_micros() as f64 / 1000000f64;
+            println!(
+                "{:.0} allocs/s\tpeak {} KB used",
+                (workers * rounds) as f64 / sec,
+                peak / 1024
+            );
+
+            Ok(())
+        } else {
+            panic!("unsupported os: android");
+        }
+    }
+}
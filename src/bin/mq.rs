@@ -3,8 +3,9 @@ use ipc::Result;
 
 cfg_if! {
     if #[cfg(not(target_os = "android"))] {
-        use ipc::flags;
+        use ipc::flags::{Mode, OpenFlags};
         use ipc::mq::MessageQueue;
+        use ipc::process::Fork;
         use std::io::{Read, Write};
         use std::time::Instant;
         use std::{env, process};
@@ -23,7 +24,11 @@ fn main() -> Result<()> {
             let size: isize = args[1].parse()?;
             let count: isize = args[2].parse()?;
 
-            let mut msg_queue = MessageQueue::open("/mq_test", flags::O_CREAT | flags::O_RDWR, 0o666)?;
+            let mut msg_queue = MessageQueue::open(
+                "/mq_test",
+                OpenFlags::CREAT | OpenFlags::RDWR,
+                Mode::from_bits_truncate(0o666),
+            )?;
             let mut attribute = msg_queue.attributes()?;
             attribute.set_max_message_count(10000);
             msg_queue.set_attributes(&attribute)?;
@@ -32,7 +37,7 @@ fn main() -> Result<()> {
             buf.resize(buf.capacity(), 0);
 
             match ipc::fork()? {
-                0 => {
+                Fork::Child => {
                     let mut sum: isize = 0;
                     for _ in 0..count {
                         sum += msg_queue.read(&mut buf)? as isize;
@@ -41,7 +46,7 @@ fn main() -> Result<()> {
                         eprintln!("sum error: {} != {}", sum, count * size);
                     }
                 }
-                pid => {
+                Fork::Parent(mut child) => {
                     let start = Instant::now();
                     for _ in 0..count {
                         let tmp = &buf[..size as usize];
@@ -58,7 +63,7 @@ fn main() -> Result<()> {
                         count as f64 / sec
                     );
 
-                    ipc::waitpid(pid, 0)?;
+                    child.wait()?;
                     msg_queue.unlink_self()?;
                 }
             }
@@ -1,3 +1,4 @@
+use ipc::process::Fork;
 use ipc::Result;
 use std::env;
 use std::io::{Read, Write};
@@ -20,26 +21,24 @@ fn main() -> Result<()> {
     buf.resize(buf.capacity(), 0);
 
     match ipc::fork()? {
-        0 => {
+        Fork::Child => {
             drop(writer);
 
             let mut sum: isize = 0;
             for _ in 0..count {
-                sum += reader.read(&mut buf)? as isize;
+                reader.read_exact(&mut buf)?;
+                sum += buf.len() as isize;
             }
             if sum != count * size {
                 eprintln!("sum error: {} != {}", sum, count * size);
             }
         }
-        pid => {
+        Fork::Parent(mut child) => {
             drop(reader);
 
             let start = Instant::now();
             for _ in 0..count {
-                if writer.write(&buf)? != buf.len() {
-                    eprintln!("write error");
-                    process::exit(1);
-                }
+                writer.write_all(&buf)?;
             }
             let duration = start.elapsed();
             let sec = duration.as_micros() as f64 / 1000000f64;
@@ -49,7 +48,7 @@ fn main() -> Result<()> {
                 count as f64 / sec
             );
 
-            ipc::waitpid(pid, 0)?;
+            child.wait()?;
         }
     }
 
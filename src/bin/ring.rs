@@ -3,12 +3,13 @@ use ipc::Result;
 
 cfg_if! {
     if #[cfg(not(target_os = "android"))] {
-        use ipc::flags;
+        use ipc::event::{ResetMode, SharedEvent};
+        use ipc::process::Fork;
         use ipc::ring::Buffer;
-        use ipc::sem::{Semaphore, SemaphoreLike};
+        use ipc::shm::Shm;
         use std::io::{Read, Write};
         use std::time::Instant;
-        use std::{env, process};
+        use std::{env, mem, process};
     }
 }
 
@@ -27,12 +28,13 @@ fn main() -> Result<()> {
             let mut buf = Vec::with_capacity(size as _);
             buf.resize(buf.capacity(), 0);
 
-            let sem = Semaphore::open("/sem_test", flags::O_CREAT | flags::O_RDWR, 0o666, 0)?;
+            let shm = Shm::anonymous(mem::size_of::<SharedEvent>())?;
+            let ready = unsafe { shm.place(0, SharedEvent::new(ResetMode::Manual))? };
 
             match ipc::fork()? {
-                0 => {
+                Fork::Child => {
                     let mut ring_buf = Buffer::new("/shm_ring", true, size as _)?;
-                    sem.post();
+                    ready.set()?;
 
                     let mut sum: isize = 0;
                     loop {
@@ -44,18 +46,13 @@ fn main() -> Result<()> {
                     }
                 }
 
-                pid => {
-                    sem.wait();
-                    sem.unlink_self();
+                Fork::Parent(mut child) => {
+                    ready.wait()?;
 
                     let mut ring_buf = Buffer::new("/shm_ring", false, size as _)?;
                     let start = Instant::now();
                     for _ in 0..count {
-                        let mut tmp = &buf[..];
-                        while tmp.len() > 0 {
-                            let n = ring_buf.write(tmp)?;
-                            tmp = &tmp[n..];
-                        }
+                        ring_buf.write_all(&buf)?;
                     }
                     let duration = start.elapsed();
                     let sec = duration.as_micros() as f64 / 1000000f64;
@@ -64,7 +61,7 @@ fn main() -> Result<()> {
                         (size * count) as f64 / sec / (1024 * 1024) as f64,
                         count as f64 / sec
                     );
-                    ipc::waitpid(pid, 0)?;
+                    child.wait()?;
                 }
             }
 
@@ -0,0 +1,80 @@
+use ipc::flags::SpliceFlags;
+use ipc::process::Fork;
+use ipc::Result;
+use std::alloc::{self, Layout};
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, IoSlice};
+use std::time::Instant;
+use std::{process, slice};
+
+const PAGE_SIZE: usize = 4096;
+
+fn main() -> Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() < 3 {
+        eprintln!("wrong argument count (< 3)");
+        process::exit(1);
+    }
+
+    let size: isize = args[1].parse()?;
+    let count: isize = args[2].parse()?;
+
+    let (mut reader, mut writer) = ipc::pipe::pipe()?;
+
+    if size <= 0 {
+        eprintln!("size must be positive");
+        process::exit(1);
+    }
+    // vmsplice 按整页把用户内存挂进管道，缓冲区要页对齐
+    let layout = Layout::from_size_align(size as _, PAGE_SIZE)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let ptr = unsafe { alloc::alloc_zeroed(layout) };
+    if ptr.is_null() {
+        alloc::handle_alloc_error(layout);
+    }
+    let buf = unsafe { slice::from_raw_parts(ptr, size as _) };
+
+    match ipc::fork()? {
+        Fork::Child => {
+            drop(writer);
+
+            // 数据直接从管道搬到 /dev/null，不经过用户态
+            let null = OpenOptions::new().write(true).open("/dev/null")?;
+            let mut sum: isize = 0;
+            while sum < count * size {
+                let n = reader.splice_to(&null, size as _, SpliceFlags::MOVE)?;
+                if n == 0 {
+                    break;
+                }
+                sum += n as isize;
+            }
+            if sum != count * size {
+                eprintln!("sum error: {} != {}", sum, count * size);
+            }
+        }
+        Fork::Parent(mut child) => {
+            drop(reader);
+
+            let start = Instant::now();
+            for _ in 0..count {
+                let mut tmp = buf;
+                while !tmp.is_empty() {
+                    let n = writer.vmsplice(&[IoSlice::new(tmp)], SpliceFlags::GIFT)?;
+                    tmp = &tmp[n..];
+                }
+            }
+            let duration = start.elapsed();
+            let sec = duration.as_micros() as f64 / 1000000f64;
+            println!(
+                "{:.0} MB/s\t{:.0} msgs/s",
+                (size * count) as f64 / sec / (1024 * 1024) as f64,
+                count as f64 / sec
+            );
+
+            child.wait()?;
+        }
+    }
+
+    Ok(())
+}
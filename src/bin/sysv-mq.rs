@@ -0,0 +1,69 @@
+use cfg_if::cfg_if;
+use ipc::Result;
+
+cfg_if! {
+    if #[cfg(not(target_os = "android"))] {
+        use ipc::flags::{IpcFlags, Mode};
+        use ipc::process::Fork;
+        use ipc::sysv::{Key, MessageQueue, MessageType};
+        use std::time::Instant;
+        use std::{env, process};
+    }
+}
+
+fn main() -> Result<()> {
+    cfg_if! {
+        if #[cfg(not(target_os = "android"))] {
+            let args = env::args().collect::<Vec<_>>();
+            if args.len() < 3 {
+                eprintln!("wrong argument count (< 3)");
+                process::exit(1);
+            }
+
+            let size: isize = args[1].parse()?;
+            let count: isize = args[2].parse()?;
+
+            let mut msg_queue = MessageQueue::open(
+                Key::PRIVATE,
+                IpcFlags::CREAT,
+                Mode::from_bits_truncate(0o666),
+            )?;
+
+            let mut buf = Vec::with_capacity(size as _);
+            buf.resize(buf.capacity(), 0);
+
+            match ipc::fork()? {
+                Fork::Child => {
+                    let mut sum: isize = 0;
+                    for _ in 0..count {
+                        let (_, n) = msg_queue.recv(MessageType::Any, &mut buf)?;
+                        sum += n as isize;
+                    }
+                    if sum != count * size {
+                        eprintln!("sum error: {} != {}", sum, count * size);
+                    }
+                }
+                Fork::Parent(mut child) => {
+                    let start = Instant::now();
+                    for _ in 0..count {
+                        msg_queue.send(1, &buf)?;
+                    }
+                    let duration = start.elapsed();
+                    let sec = duration.as_micros() as f64 / 1000000f64;
+                    println!(
+                        "{:.0} MB/s\t{:.0} msgs/s",
+                        (size * count) as f64 / sec / (1024 * 1024) as f64,
+                        count as f64 / sec
+                    );
+
+                    child.wait()?;
+                    msg_queue.remove()?;
+                }
+            }
+
+            Ok(())
+        } else {
+            panic!("unsupported os: android");
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use cfg_if::cfg_if;
+use ipc::Result;
+
+cfg_if! {
+    if #[cfg(not(target_os = "android"))] {
+        use ipc::flags::{IpcFlags, Mode};
+        use ipc::process::Fork;
+        use ipc::sysv::{Key, SemOp, SemaphoreSet, SharedMemory};
+        use std::time::Instant;
+        use std::{env, process};
+    }
+}
+
+// 0: 共享内存空闲，1: 共享内存有数据
+#[cfg(not(target_os = "android"))]
+const EMPTY: u16 = 0;
+#[cfg(not(target_os = "android"))]
+const FULL: u16 = 1;
+
+fn main() -> Result<()> {
+    cfg_if! {
+        if #[cfg(not(target_os = "android"))] {
+            let args = env::args().collect::<Vec<_>>();
+            if args.len() < 3 {
+                eprintln!("wrong argument count (< 3)");
+                process::exit(1);
+            }
+
+            let size: isize = args[1].parse()?;
+            let count: isize = args[2].parse()?;
+
+            let mut buf = Vec::with_capacity(size as _);
+            buf.resize(buf.capacity(), 0);
+
+            let perm = Mode::from_bits_truncate(0o666);
+            let mut shm = SharedMemory::open(Key::PRIVATE, size as _, IpcFlags::CREAT, perm)?;
+            shm.remove()?;
+            let sems = SemaphoreSet::open(Key::PRIVATE, 2, IpcFlags::CREAT, perm)?;
+            sems.set_values(&[1, 0])?;
+
+            match ipc::fork()? {
+                Fork::Child => {
+                    let mut sum: isize = 0;
+                    for _ in 0..count {
+                        sems.op(&[SemOp::wait(FULL)])?;
+                        buf.copy_from_slice(shm.as_slice());
+                        sems.op(&[SemOp::post(EMPTY)])?;
+                        sum += buf.len() as isize;
+                    }
+                    if sum != count * size {
+                        eprintln!("sum error: {} != {}", sum, count * size);
+                    }
+                }
+                Fork::Parent(mut child) => {
+                    let start = Instant::now();
+                    for _ in 0..count {
+                        sems.op(&[SemOp::wait(EMPTY)])?;
+                        shm.as_mut_slice().copy_from_slice(&buf);
+                        sems.op(&[SemOp::post(FULL)])?;
+                    }
+                    let duration = start.elapsed();
+                    let sec = duration.as_micros() as f64 / 1000000f64;
+                    println!(
+                        "{:.0} MB/s\t{:.0} msgs/s",
+                        (size * count) as f64 / sec / (1024 * 1024) as f64,
+                        count as f64 / sec
+                    );
+
+                    child.wait()?;
+                    sems.remove()?;
+                }
+            }
+
+            Ok(())
+        } else {
+            panic!("unsupported os: android");
+        }
+    }
+}
@@ -1,10 +1,11 @@
-use ipc::{flags, Result};
-use std::env;
+use ipc::flags::{Mode, OpenFlags};
+use ipc::process::Fork;
+use ipc::sem::{Semaphore, SemaphoreLike};
+use ipc::Result;
 use std::io::{Read, Write};
 use std::net::{Shutdown, TcpListener, TcpStream};
-use std::process;
 use std::time::Instant;
-use ipc::sem::{Semaphore, SemaphoreLike};
+use std::{env, process};
 
 fn main() -> Result<()> {
     let args = env::args().collect::<Vec<_>>();
@@ -19,12 +20,17 @@ fn main() -> Result<()> {
     let mut buf = Vec::with_capacity(size as _);
     buf.resize(buf.capacity(), 0);
 
-    let sem = Semaphore::open("/sem_test", flags::O_CREAT | flags::O_RDWR, 0o666, 0)?;
+    let sem = Semaphore::open(
+        "/sem_test",
+        OpenFlags::CREAT | OpenFlags::RDWR,
+        Mode::from_bits_truncate(0o666),
+        0,
+    )?;
 
     match ipc::fork()? {
-        0 => {
+        Fork::Child => {
             let listener = TcpListener::bind("0.0.0.0:18899")?;
-            sem.post();
+            sem.post()?;
 
             let (mut tcp, _) = listener.accept()?;
             let mut sum: isize = 0;
@@ -39,17 +45,14 @@ fn main() -> Result<()> {
                 eprintln!("sum error: {} != {}", sum, count * size);
             }
         }
-        pid => {
-            sem.wait();
+        Fork::Parent(mut child) => {
+            sem.wait()?;
             sem.unlink_self();
 
             let mut tcp = TcpStream::connect("0.0.0.0:18899")?;
             let start = Instant::now();
             for _ in 0..count {
-                if tcp.write(&buf)? != buf.len() {
-                    eprintln!("write error");
-                    process::exit(1);
-                }
+                tcp.write_all(&buf)?;
             }
             let duration = start.elapsed();
             let sec = duration.as_micros() as f64 / 1000000f64;
@@ -61,7 +64,7 @@ fn main() -> Result<()> {
             // 防止死锁
             tcp.shutdown(Shutdown::Both)?;
 
-            ipc::waitpid(pid, 0)?;
+            child.wait()?;
         }
     }
 
@@ -1,3 +1,4 @@
+use ipc::process::Fork;
 use ipc::Result;
 use std::env;
 use std::os::unix::net::UnixDatagram;
@@ -20,7 +21,7 @@ fn main() -> Result<()> {
     let (datagram1, datagram2) = UnixDatagram::pair()?;
 
     match ipc::fork()? {
-        0 => {
+        Fork::Child => {
             let mut sum: isize = 0;
             for _ in 0..count {
                 sum += datagram1.recv(&mut buf)? as isize;
@@ -30,7 +31,7 @@ fn main() -> Result<()> {
             }
         }
 
-        pid => {
+        Fork::Parent(mut child) => {
             let start = Instant::now();
             for _ in 0..count {
                 if datagram2.send(&buf)? != buf.len() {
@@ -46,7 +47,7 @@ fn main() -> Result<()> {
                 count as f64 / sec
             );
 
-            ipc::waitpid(pid, 0)?;
+            child.wait()?;
         }
     }
 
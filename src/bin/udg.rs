@@ -1,5 +1,7 @@
+use ipc::flags::{Mode, OpenFlags};
+use ipc::process::Fork;
 use ipc::sem::{Semaphore, SemaphoreLike};
-use ipc::{flags, Result};
+use ipc::Result;
 use std::os::unix::net::UnixDatagram;
 use std::time::Instant;
 use std::{env, fs, process};
@@ -18,12 +20,17 @@ fn main() -> Result<()> {
     buf.resize(buf.capacity(), 0);
 
     let path = "./udg-test";
-    let sem = Semaphore::open("/sem_test", flags::O_CREAT | flags::O_RDWR, 0o666, 0)?;
+    let sem = Semaphore::open(
+        "/sem_test",
+        OpenFlags::CREAT | OpenFlags::RDWR,
+        Mode::from_bits_truncate(0o666),
+        0,
+    )?;
 
     match ipc::fork()? {
-        0 => {
+        Fork::Child => {
             let datagram = UnixDatagram::bind(path)?;
-            sem.post();
+            sem.post()?;
 
             let mut sum: isize = 0;
             for _ in 0..count {
@@ -34,8 +41,8 @@ fn main() -> Result<()> {
             }
         }
 
-        pid => {
-            sem.wait();
+        Fork::Parent(mut child) => {
+            sem.wait()?;
             sem.unlink_self();
 
             let datagram = UnixDatagram::unbound()?;
@@ -55,7 +62,7 @@ fn main() -> Result<()> {
                 count as f64 / sec
             );
 
-            ipc::waitpid(pid, 0)?;
+            child.wait()?;
             let _ = fs::remove_file(path);
         }
     }
@@ -1,10 +1,11 @@
+use ipc::flags::{Mode, OpenFlags};
+use ipc::process::Fork;
 use ipc::sem::{Semaphore, SemaphoreLike};
-use ipc::{flags, Result};
+use ipc::Result;
 use log::{error, info};
 use std::net::UdpSocket;
-use std::process;
 use std::time::{Duration, Instant};
-use std::{env, thread};
+use std::{env, process, thread};
 
 fn main() -> Result<()> {
     let args = env::args().collect::<Vec<_>>();
@@ -19,10 +20,15 @@ fn main() -> Result<()> {
     let mut buf = Vec::with_capacity(size as _);
     buf.resize(buf.capacity(), 0);
 
-    let sem = Semaphore::open("/sem_test", flags::O_RDWR | flags::O_CREAT, 0o666, 0)?;
+    let sem = Semaphore::open(
+        "/sem_test",
+        OpenFlags::CREAT | OpenFlags::RDWR,
+        Mode::from_bits_truncate(0o666),
+        0,
+    )?;
 
     match ipc::fork()? {
-        0 => {
+        Fork::Child => {
             info!("pid: {}", ipc::getpid());
 
             let mut sum: isize = 0;
@@ -36,7 +42,7 @@ fn main() -> Result<()> {
                 }
             };
             // notify parent process
-            sem.post();
+            sem.post()?;
             // 超时时间到达后，无论如何都要退出
             // 有可能出现丢包，造成没读到 count 个包无法退出循环
             udp_svr.set_read_timeout(Some(Duration::from_secs(1)))?;
@@ -62,11 +68,11 @@ fn main() -> Result<()> {
                 error!("sum error: {} != {}", sum, count * size);
             }
         }
-        pid => {
+        Fork::Parent(mut child) => {
             info!("pid: {}", ipc::getpid());
 
             // wait for peer to start
-            sem.wait();
+            sem.wait()?;
             sem.unlink_self();
 
             let udp_cli = loop {
@@ -107,8 +113,8 @@ fn main() -> Result<()> {
                 count as f64 / sec
             );
 
-            let (pid, status) = ipc::waitpid(pid, 0)?;
-            info!("parent exit! child pid: {}, status: {}", pid, status);
+            let status = child.wait()?;
+            info!("parent exit! child pid: {}, status: {}", child.id(), status);
         }
     }
 
@@ -1,3 +1,4 @@
+use ipc::process::Fork;
 use ipc::Result;
 use std::env;
 use std::io::{Read, Write};
@@ -21,23 +22,21 @@ fn main() -> Result<()> {
     let (mut stream1, mut stream2) = UnixStream::pair()?;
 
     match ipc::fork()? {
-        0 => {
+        Fork::Child => {
             let mut sum: isize = 0;
             for _ in 0..count {
-                sum += stream1.read(&mut buf)? as isize;
+                stream1.read_exact(&mut buf)?;
+                sum += buf.len() as isize;
             }
             if sum != count * size {
                 eprintln!("sum error: {} != {}", sum, count * size);
             }
         }
 
-        pid => {
+        Fork::Parent(mut child) => {
             let start = Instant::now();
             for _ in 0..count {
-                if stream2.write(&buf)? != buf.len() {
-                    eprintln!("write error");
-                    process::exit(1);
-                }
+                stream2.write_all(&buf)?;
             }
             let duration = start.elapsed();
             let sec = duration.as_micros() as f64 / 1000000f64;
@@ -47,7 +46,7 @@ fn main() -> Result<()> {
                 count as f64 / sec
             );
 
-            ipc::waitpid(pid, 0)?;
+            child.wait()?;
         }
     }
 
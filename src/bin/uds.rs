@@ -1,5 +1,7 @@
+use ipc::flags::{Mode, OpenFlags};
+use ipc::process::Fork;
 use ipc::sem::{Semaphore, SemaphoreLike};
-use ipc::{flags, Result};
+use ipc::Result;
 use std::io::{Read, Write};
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::time::Instant;
@@ -19,34 +21,37 @@ fn main() -> Result<()> {
     buf.resize(buf.capacity(), 0);
 
     let path = "./uds-test";
-    let sem = Semaphore::open("/sem_test", flags::O_RDWR | flags::O_CREAT, 0o666, 0)?;
+    let sem = Semaphore::open(
+        "/sem_test",
+        OpenFlags::CREAT | OpenFlags::RDWR,
+        Mode::from_bits_truncate(0o666),
+        0,
+    )?;
 
     match ipc::fork()? {
-        0 => {
+        Fork::Child => {
             let listener = UnixListener::bind(path)?;
-            sem.post();
+            sem.post()?;
 
             let (mut stream, _) = listener.accept()?;
             let mut sum: isize = 0;
             for _ in 0..count {
-                sum += stream.read(&mut buf)? as isize;
+                stream.read_exact(&mut buf)?;
+                sum += buf.len() as isize;
             }
             if sum != count * size {
                 eprintln!("sum error: {} != {}", sum, count * size);
             }
         }
 
-        pid => {
-            sem.wait();
+        Fork::Parent(mut child) => {
+            sem.wait()?;
             sem.unlink_self();
 
             let mut stream = UnixStream::connect(path)?;
             let start = Instant::now();
             for _ in 0..count {
-                if stream.write(&buf)? != buf.len() {
-                    eprintln!("write error");
-                    process::exit(1);
-                }
+                stream.write_all(&buf)?;
             }
             let duration = start.elapsed();
             let sec = duration.as_micros() as f64 / 1000000f64;
@@ -56,7 +61,7 @@ fn main() -> Result<()> {
                 count as f64 / sec
             );
 
-            ipc::waitpid(pid, 0)?;
+            child.wait()?;
             let _ = fs::remove_file(path);
         }
     }
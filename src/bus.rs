@@ -0,0 +1,460 @@
+//! A named publish/subscribe bus in shared memory.
+//!
+//! The bus is a registry segment under its name, listing the topics with
+//! their layout, subscriber count and publish sequence. Each topic is a
+//! broadcast ring of fixed-size slots in its own segment, `<bus>.<topic>`,
+//! created the first time anyone publishes or subscribes to it.
+//!
+//! Publishers never wait for subscribers: a subscriber that falls more than
+//! a ring behind skips what was overwritten and counts it in
+//! [`Subscriber::missed`]. Segments outlive the processes using them until
+//! [`Bus::unlink`] removes them. A process that dies while publishing stalls
+//! the topic's other publishers, and one that dies while subscribed leaves
+//! the subscriber count too high.
+
+use crate::futex;
+use crate::shm::{Shm, ShmSafe};
+use crate::{Error, Result};
+use std::cell::UnsafeCell;
+use std::sync::atomic::{self, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use std::{cmp, io, mem, ptr, slice, str, thread};
+
+/// The most topics one bus can hold.
+pub const MAX_TOPICS: usize = 64;
+/// The longest topic name, in bytes.
+pub const MAX_TOPIC_NAME: usize = 47;
+
+const REGISTRY_SIZE: usize = MAX_TOPICS * mem::size_of::<TopicEntry>();
+
+pub const DEFAULT_SLOTS: u32 = 1024;
+pub const DEFAULT_SLOT_SIZE: u32 = 1024;
+
+const EMPTY: u32 = 0;
+const CREATING: u32 = 1;
+const READY: u32 = 2;
+
+/// One registry entry. An all-zero entry is an empty one, so a freshly
+/// created registry needs no initialization.
+#[repr(C)]
+struct TopicEntry {
+    state: AtomicU32,
+    subscribers: AtomicU32,
+    slots: AtomicU32,
+    slot_size: AtomicU32,
+    // 发布序号：reserve 是下一个要领取的，head 是已提交的消息数
+    reserve: AtomicU64,
+    head: AtomicU64,
+    notify: AtomicU32,
+    waiters: AtomicU32,
+    name_len: AtomicU32,
+    name: UnsafeCell<[u8; MAX_TOPIC_NAME]>,
+}
+
+// name 只在 CREATING 期间由创建者写入，READY 之后只读
+unsafe impl Sync for TopicEntry {}
+unsafe impl ShmSafe for TopicEntry {}
+
+impl TopicEntry {
+    fn name(&self) -> &[u8] {
+        let len = cmp::min(
+            self.name_len.load(Ordering::Relaxed) as usize,
+            MAX_TOPIC_NAME,
+        );
+        unsafe { &(*self.name.get())[..len] }
+    }
+
+    /// Waits out a creation in progress and returns the settled state.
+    fn settled_state(&self) -> u32 {
+        loop {
+            match self.state.load(Ordering::Acquire) {
+                CREATING => thread::yield_now(),
+                state => return state,
+            }
+        }
+    }
+}
+
+#[repr(C)]
+struct SlotHeader {
+    // 偶数 2(s+1) 表示第 s 条消息已写完，奇数表示正在写
+    stamp: AtomicU64,
+    len: AtomicU32,
+    _pad: u32,
+}
+
+unsafe impl ShmSafe for SlotHeader {}
+
+fn slot_stride(slot_size: u32) -> usize {
+    (mem::size_of::<SlotHeader>() + slot_size as usize + 63) & !63
+}
+
+/// Where a new subscriber starts reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Start {
+    /// With the next message published after joining.
+    Latest,
+    /// With the oldest message the ring still holds.
+    Oldest,
+}
+
+/// A snapshot of one topic's registry entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicInfo {
+    pub name: String,
+    pub subscribers: u32,
+    /// The sequence number of the newest message, if any has been published.
+    pub last_seq: Option<u64>,
+    pub slots: u32,
+    pub slot_size: u32,
+}
+
+/// A handle on the bus registered under `name`.
+#[derive(Debug)]
+pub struct Bus {
+    registry: Shm,
+    name: String,
+    slots: u32,
+    slot_size: u32,
+}
+
+impl Bus {
+    /// Opens the bus, creating its registry if this is the first process to
+    /// use it.
+    pub fn open(name: &str) -> Result<Bus> {
+        Ok(Self::with_registry(
+            name,
+            Shm::open_or_create(name, REGISTRY_SIZE)?,
+        ))
+    }
+
+    fn with_registry(name: &str, registry: Shm) -> Bus {
+        Bus {
+            registry,
+            name: name.to_string(),
+            slots: DEFAULT_SLOTS,
+            slot_size: DEFAULT_SLOT_SIZE,
+        }
+    }
+
+    /// Sets the ring layout of topics this handle creates. Topics that
+    /// already exist keep the layout they were created with.
+    pub fn set_topic_layout(&mut self, slots: u32, slot_size: u32) -> &mut Bus {
+        self.slots = slots;
+        self.slot_size = slot_size;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn publisher(&self, topic: &str) -> Result<Publisher<'_>> {
+        Ok(Publisher {
+            topic: self.topic(topic)?,
+        })
+    }
+
+    pub fn subscribe(&self, topic: &str, start: Start) -> Result<Subscriber<'_>> {
+        let topic = self.topic(topic)?;
+        topic.entry.subscribers.fetch_add(1, Ordering::Relaxed);
+        let head = topic.entry.head.load(Ordering::Acquire);
+        let next = match start {
+            Start::Latest => head,
+            Start::Oldest => head.saturating_sub(topic.slots as u64),
+        };
+        Ok(Subscriber {
+            buf: vec![0; topic.slot_size as usize],
+            topic,
+            next,
+            missed: 0,
+        })
+    }
+
+    /// Lists the topics created so far.
+    pub fn topics(&self) -> Vec<TopicInfo> {
+        self.entries()
+            .filter(|entry| entry.settled_state() == READY)
+            .map(|entry| TopicInfo {
+                name: String::from_utf8_lossy(entry.name()).into_owned(),
+                subscribers: entry.subscribers.load(Ordering::Relaxed),
+                last_seq: entry.head.load(Ordering::Acquire).checked_sub(1),
+                slots: entry.slots.load(Ordering::Relaxed),
+                slot_size: entry.slot_size.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Removes the registry and every topic segment of the bus `name`.
+    /// Processes that have it open keep working on the old segments. Fails
+    /// with `ENOENT` if there is no such bus.
+    pub fn unlink(name: &str) -> Result<()> {
+        // 不带 O_CREAT 打开，不存在的总线不会先被建出来
+        let bus = Self::with_registry(name, Shm::open(name, REGISTRY_SIZE, false)?);
+        for info in bus.topics() {
+            match Shm::unlink(&topic_segment(name, &info.name)) {
+                Err(err) if err.as_errno() == Some(libc::ENOENT) => {}
+                result => result?,
+            }
+        }
+        Shm::unlink(name)
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &TopicEntry> {
+        // 注册表按 MAX_TOPICS 个条目映射，每个下标都在界内
+        (0..MAX_TOPICS).map(move |index| unsafe {
+            &*self
+                .registry
+                .slot_unchecked::<TopicEntry>(index * mem::size_of::<TopicEntry>())
+        })
+    }
+
+    fn topic(&self, name: &str) -> Result<Topic<'_>> {
+        if name.is_empty() || name.len() > MAX_TOPIC_NAME || name.contains(&['/', '\0'][..]) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid topic name").into());
+        }
+        'scan: loop {
+            for entry in self.entries() {
+                match entry.settled_state() {
+                    READY if entry.name() == name.as_bytes() => return self.attach(name, entry),
+                    READY => continue,
+                    _ => {}
+                }
+                // 条目只会按顺序占用，第一个空条目之后不会有已建的 topic
+                if entry
+                    .state
+                    .compare_exchange(EMPTY, CREATING, Ordering::Acquire, Ordering::Relaxed)
+                    .is_err()
+                {
+                    continue 'scan;
+                }
+                return self.create(name, entry);
+            }
+            return Err(io::Error::new(io::ErrorKind::Other, "bus registry full").into());
+        }
+    }
+
+    fn create<'a>(&'a self, name: &str, entry: &'a TopicEntry) -> Result<Topic<'a>> {
+        let size = self.slots as usize * slot_stride(self.slot_size);
+        let shm = if self.slots == 0 {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, "topic without slots").into())
+        } else {
+            Shm::open_or_create(&topic_segment(&self.name, name), size)
+        };
+        let shm = match shm {
+            Ok(mut shm) => {
+                // 可能是上一次残留的段，槽位要清零
+                unsafe { ptr::write_bytes(shm.as_mut_ptr(), 0, size) };
+                shm
+            }
+            Err(err) => {
+                entry.state.store(EMPTY, Ordering::Release);
+                return Err(err);
+            }
+        };
+        unsafe {
+            (*entry.name.get())[..name.len()].copy_from_slice(name.as_bytes());
+        }
+        entry.name_len.store(name.len() as _, Ordering::Relaxed);
+        entry.slots.store(self.slots, Ordering::Relaxed);
+        entry.slot_size.store(self.slot_size, Ordering::Relaxed);
+        entry.state.store(READY, Ordering::Release);
+        Ok(Topic {
+            entry,
+            shm,
+            slots: self.slots,
+            slot_size: self.slot_size,
+        })
+    }
+
+    fn attach<'a>(&'a self, name: &str, entry: &'a TopicEntry) -> Result<Topic<'a>> {
+        let slots = entry.slots.load(Ordering::Relaxed);
+        let slot_size = entry.slot_size.load(Ordering::Relaxed);
+        let size = slots as usize * slot_stride(slot_size);
+        Ok(Topic {
+            entry,
+            shm: Shm::open(&topic_segment(&self.name, name), size, false)?,
+            slots,
+            slot_size,
+        })
+    }
+}
+
+fn topic_segment(bus: &str, topic: &str) -> String {
+    format!("{}.{}", bus, topic)
+}
+
+struct Topic<'a> {
+    entry: &'a TopicEntry,
+    shm: Shm,
+    slots: u32,
+    slot_size: u32,
+}
+
+impl Topic<'_> {
+    fn name(&self) -> &str {
+        str::from_utf8(self.entry.name()).unwrap_or_default()
+    }
+
+    fn slot(&self, seq: u64) -> (&SlotHeader, *mut u8) {
+        let offset = (seq % self.slots as u64) as usize * slot_stride(self.slot_size);
+        // 段按 slots 个 64 字节对齐的槽映射，取模后的偏移总在界内
+        unsafe {
+            let header = &*self.shm.slot_unchecked::<SlotHeader>(offset);
+            let data = self.shm.as_ptr().add(offset + mem::size_of::<SlotHeader>());
+            (header, data as *mut u8)
+        }
+    }
+}
+
+/// Publishes to one topic. Any number of processes may publish to the same
+/// topic; their messages are ordered by sequence number.
+pub struct Publisher<'a> {
+    topic: Topic<'a>,
+}
+
+impl Publisher<'_> {
+    pub fn topic(&self) -> &str {
+        self.topic.name()
+    }
+
+    /// Publishes `msg` and returns its sequence number. Fails with
+    /// [`Error::MessageTooLarge`] if `msg` doesn't fit in a slot.
+    pub fn publish(&self, msg: &[u8]) -> Result<u64> {
+        let topic = &self.topic;
+        if msg.len() > topic.slot_size as usize {
+            return Err(Error::MessageTooLarge {
+                max: topic.slot_size as usize,
+            });
+        }
+        let entry = topic.entry;
+        // 领号排队，轮到自己才写，多个发布者按序号依次提交
+        let seq = entry.reserve.fetch_add(1, Ordering::Relaxed);
+        while entry.head.load(Ordering::Acquire) != seq {
+            thread::yield_now();
+        }
+        let (header, data) = topic.slot(seq);
+        header.stamp.store(2 * seq + 1, Ordering::Relaxed);
+        atomic::fence(Ordering::Release);
+        unsafe { ptr::copy_nonoverlapping(msg.as_ptr(), data, msg.len()) };
+        header.len.store(msg.len() as _, Ordering::Relaxed);
+        header.stamp.store(2 * seq + 2, Ordering::Release);
+        entry.head.store(seq + 1, Ordering::SeqCst);
+        entry.notify.fetch_add(1, Ordering::SeqCst);
+        if entry.waiters.load(Ordering::SeqCst) > 0 {
+            futex::futex_wake(futex::as_word(&entry.notify), i32::MAX as u32)?;
+        }
+        Ok(seq)
+    }
+}
+
+/// Receives every message published to one topic after its start point.
+pub struct Subscriber<'a> {
+    topic: Topic<'a>,
+    next: u64,
+    missed: u64,
+    buf: Vec<u8>,
+}
+
+impl Subscriber<'_> {
+    pub fn topic(&self) -> &str {
+        self.topic.name()
+    }
+
+    /// The sequence number of the next message to receive.
+    pub fn next_seq(&self) -> u64 {
+        self.next
+    }
+
+    /// How many messages were overwritten before this subscriber got to them.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    /// Waits for the next message and returns its sequence number and bytes.
+    pub fn recv(&mut self) -> Result<(u64, &[u8])> {
+        loop {
+            if let Some((seq, len)) = self.poll() {
+                return Ok((seq, &self.buf[..len]));
+            }
+            self.wait(None)?;
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Option<(u64, &[u8])> {
+        let (seq, len) = self.poll()?;
+        Some((seq, &self.buf[..len]))
+    }
+
+    /// Returns `None` if nothing was published before `timeout` elapsed.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<(u64, &[u8])>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some((seq, len)) = self.poll() {
+                return Ok(Some((seq, &self.buf[..len])));
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(None);
+            }
+            self.wait(Some(left))?;
+        }
+    }
+
+    /// Copies the next message into `buf` if one is ready.
+    fn poll(&mut self) -> Option<(u64, usize)> {
+        let topic = &self.topic;
+        let slots = topic.slots as u64;
+        loop {
+            let head = topic.entry.head.load(Ordering::Acquire);
+            if self.next >= head {
+                return None;
+            }
+            if head - self.next > slots {
+                self.missed += head - slots - self.next;
+                self.next = head - slots;
+            }
+            let seq = self.next;
+            let (header, data) = topic.slot(seq);
+            let stamp = header.stamp.load(Ordering::Acquire);
+            if stamp == 2 * seq + 2 {
+                let len = cmp::min(header.len.load(Ordering::Relaxed), topic.slot_size) as usize;
+                unsafe {
+                    let src = slice::from_raw_parts(data as *const u8, len);
+                    self.buf[..len].copy_from_slice(src);
+                }
+                atomic::fence(Ordering::Acquire);
+                if header.stamp.load(Ordering::Relaxed) == stamp {
+                    self.next += 1;
+                    return Some((seq, len));
+                }
+            }
+            // 读的过程中槽位被新消息覆盖了
+            self.missed += 1;
+            self.next += 1;
+        }
+    }
+
+    fn wait(&self, timeout: Option<Duration>) -> Result<()> {
+        let entry = self.topic.entry;
+        entry.waiters.fetch_add(1, Ordering::SeqCst);
+        let notify = entry.notify.load(Ordering::SeqCst);
+        let result = if entry.head.load(Ordering::SeqCst) > self.next {
+            Ok(())
+        } else {
+            let word = futex::as_word(&entry.notify);
+            match timeout {
+                Some(timeout) => futex::futex_timed_wait(word, notify, timeout).map(drop),
+                None => futex::futex_wait(word, notify).map(drop),
+            }
+        };
+        entry.waiters.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+}
+
+impl Drop for Subscriber<'_> {
+    fn drop(&mut self) {
+        self.topic.entry.subscribers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
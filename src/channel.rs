@@ -0,0 +1,146 @@
+//! Typed channels, the cross-process counterpart of `std::sync::mpsc`.
+//!
+//! Each value is encoded with bincode and sent as one message over a
+//! [`Transport`]. Both ends enforce a maximum encoded size, and a frame that
+//! is too large or doesn't decode is reported as an error rather than
+//! skipped.
+
+use crate::transport::{BoxedReceiver, BoxedSender, Transport};
+use crate::{Error, Result};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cmp;
+use std::marker::PhantomData;
+
+/// The size limit [`channel`] uses unless the transport's own is smaller.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Creates a connected typed channel over `transport`. Create it before
+/// `fork` and keep one end on each side.
+///
+/// Values that encode to nothing, such as `()`, can't go over packet pipes
+/// or seqpacket sockets, which don't carry empty messages.
+pub fn channel<T>(transport: &Transport) -> Result<(Sender<T>, Receiver<T>)>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let (sender, receiver) = transport.pair()?;
+    let max_size = sender
+        .max_message_size()
+        .map_or(DEFAULT_MAX_MESSAGE_SIZE, |max| {
+            cmp::min(max, DEFAULT_MAX_MESSAGE_SIZE)
+        });
+    Ok((
+        Sender::new(sender, max_size),
+        Receiver::new(receiver, max_size),
+    ))
+}
+
+pub(crate) fn options(max_size: usize) -> impl Options {
+    bincode::DefaultOptions::new().with_limit(max_size as u64)
+}
+
+/// The sending half of a typed channel.
+pub struct Sender<T> {
+    inner: BoxedSender,
+    max_size: usize,
+    buf: Vec<u8>,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<T: Serialize> Sender<T> {
+    /// Sends values over `inner`, refusing any that encode to more than
+    /// `max_size` bytes.
+    pub fn new(inner: BoxedSender, max_size: usize) -> Sender<T> {
+        Sender {
+            inner,
+            max_size,
+            buf: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn max_message_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Fails with [`Error::MessageTooLarge`] without sending anything if
+    /// `value` encodes to more than the limit.
+    pub fn send(&mut self, value: &T) -> Result<()> {
+        self.buf.clear();
+        if let Err(err) = options(self.max_size).serialize_into(&mut self.buf, value) {
+            return Err(match *err {
+                bincode::ErrorKind::SizeLimit => Error::MessageTooLarge { max: self.max_size },
+                _ => Error::Encode(err),
+            });
+        }
+        self.inner.send(&self.buf)
+    }
+
+    pub fn into_inner(self) -> BoxedSender {
+        self.inner
+    }
+}
+
+/// The receiving half of a typed channel.
+pub struct Receiver<T> {
+    inner: BoxedReceiver,
+    max_size: usize,
+    buf: Vec<u8>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Receiver<T> {
+    /// Receives values from `inner`, rejecting frames over `max_size` bytes.
+    /// Over a message queue, `max_size` must be at least the queue's message
+    /// size.
+    pub fn new(inner: BoxedReceiver, max_size: usize) -> Receiver<T> {
+        Receiver {
+            inner,
+            max_size,
+            buf: vec![0; max_size],
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn max_message_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Receives the next value, or `None` once the sending side is gone.
+    /// An oversized frame fails with [`Error::MessageTooLarge`] and one that
+    /// doesn't decode with [`Error::Decode`]; either way the frame is
+    /// consumed and the next call moves on.
+    pub fn recv(&mut self) -> Result<Option<T>> {
+        let n = match self.inner.recv(&mut self.buf)? {
+            Some(n) => n,
+            None => return Ok(None),
+        };
+        options(self.max_size)
+            .deserialize(&self.buf[..n])
+            .map(Some)
+            .map_err(Error::Decode)
+    }
+
+    /// Iterates over received values until the sending side is gone.
+    pub fn iter(&mut self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+
+    pub fn into_inner(self) -> BoxedReceiver {
+        self.inner
+    }
+}
+
+pub struct Iter<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<'a, T: DeserializeOwned> Iterator for Iter<'a, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        self.receiver.recv().transpose()
+    }
+}
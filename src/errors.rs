@@ -1,9 +1,9 @@
 #[cfg(not(target_os = "android"))]
 use crate::mq::MessageQueue;
+use crate::process::ExitStatus;
 use crate::sem::Semaphore;
-use crate::Result;
-use std::ffi::{CStr, NulError};
-use std::panic;
+use std::ffi::NulError;
+use std::io;
 use std::str::Utf8Error;
 
 #[derive(Debug, thiserror::Error)]
@@ -14,51 +14,160 @@ pub enum Error {
     #[error("C style string nul error: {0}")]
     Null(#[from] NulError),
 
-    #[error("errno: {0}, msg: {1}")]
-    Errno(libc::c_int, String),
+    /// A failed system call: the raw `errno`, the call's name and, for named
+    /// objects, the queue, segment or path it was called on.
+    #[error("{op}{}: {}", describe_object(.object), io::Error::from_raw_os_error(*.errno))]
+    Errno {
+        errno: libc::c_int,
+        op: &'static str,
+        object: Option<String>,
+    },
 
     #[error("parse int error: {0}")]
     Int(#[from] std::num::ParseIntError),
 
     #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
+    Io(#[from] io::Error),
 
-    #[error("sem errno: {1}, msg: {2}")]
-    Sem(Semaphore, libc::c_int, String),
+    #[error("no reader has FIFO {0} open")]
+    FifoNoReader(String),
 
+    #[error("child panicked: {0}")]
+    ChildPanic(String),
+
+    /// The child ended without sending back a result.
+    #[error("child ended without a result: {0}")]
+    ChildExit(ExitStatus),
+
+    /// A message that encodes to, or arrived as, more than `max` bytes.
+    #[error("message exceeds the {max}-byte limit")]
+    MessageTooLarge { max: usize },
+
+    #[cfg(feature = "serde")]
+    #[error("cannot encode message: {0}")]
+    Encode(#[source] bincode::Error),
+
+    #[cfg(feature = "serde")]
+    #[error("corrupt message: {0}")]
+    Decode(#[source] bincode::Error),
+
+    /// An RPC handler's error, as reported by the server.
+    #[error("remote error: {0}")]
+    Remote(String),
+
+    #[error("timed out")]
+    TimedOut,
+
+    /// The other end of the connection is gone.
+    #[error("disconnected")]
+    Disconnected,
+
+    /// Hands the semaphore back along with the error that occurred on it.
+    #[error("{1}")]
+    Sem(Semaphore, #[source] Box<Error>),
+
+    /// Hands the queue back along with the error that occurred on it.
     #[cfg(not(target_os = "android"))]
-    #[error("mq errno: {1}, msg: {2}")]
-    Mq(MessageQueue, libc::c_int, String),
+    #[error("{1}")]
+    Mq(MessageQueue, #[source] Box<Error>),
+}
+
+fn describe_object(object: &Option<String>) -> String {
+    match object {
+        Some(object) => format!(" {}", object),
+        None => String::new(),
+    }
 }
 
 impl Error {
-    pub fn into_errno(self) -> (libc::c_int, String) {
+    pub(crate) fn errno(errno: libc::c_int, op: &'static str, object: Option<&str>) -> Error {
+        Error::Errno {
+            errno,
+            op,
+            object: object.map(str::to_string),
+        }
+    }
+
+    /// The raw OS error code behind this error, if there is one.
+    pub fn as_errno(&self) -> Option<libc::c_int> {
         match self {
-            Error::Errno(errno, msg) => (errno, msg),
-            _ => panic!("can't into"),
+            Error::Errno { errno, .. } => Some(*errno),
+            Error::Io(err) => err.raw_os_error(),
+            Error::FifoNoReader(_) => Some(libc::ENXIO),
+            Error::Sem(_, err) => err.as_errno(),
+            #[cfg(not(target_os = "android"))]
+            Error::Mq(_, err) => err.as_errno(),
+            _ => None,
         }
     }
 
-    pub fn into_sem(self) -> (Semaphore, libc::c_int, String) {
+    /// The name of the system call that failed.
+    pub fn as_op(&self) -> Option<&str> {
         match self {
-            Error::Sem(sem, errno, msg) => (sem, errno, msg),
-            _ => panic!("can't into"),
+            Error::Errno { op, .. } => Some(op),
+            Error::Sem(_, err) => err.as_op(),
+            #[cfg(not(target_os = "android"))]
+            Error::Mq(_, err) => err.as_op(),
+            _ => None,
         }
     }
 
+    /// The queue, segment or path the failing call was made on.
+    pub fn as_object(&self) -> Option<&str> {
+        match self {
+            Error::Errno { object, .. } => object.as_deref(),
+            Error::FifoNoReader(path) => Some(path),
+            Error::Sem(_, err) => err.as_object(),
+            #[cfg(not(target_os = "android"))]
+            Error::Mq(_, err) => err.as_object(),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Error::Io(err) => err.kind(),
+            Error::Utf8(_) | Error::Null(_) | Error::Int(_) => io::ErrorKind::InvalidInput,
+            #[cfg(feature = "serde")]
+            Error::Encode(_) => io::ErrorKind::InvalidInput,
+            Error::MessageTooLarge { .. } => io::ErrorKind::InvalidData,
+            #[cfg(feature = "serde")]
+            Error::Decode(_) => io::ErrorKind::InvalidData,
+            Error::TimedOut => io::ErrorKind::TimedOut,
+            Error::Disconnected => io::ErrorKind::ConnectionAborted,
+            _ => match self.as_errno() {
+                Some(errno) => io::Error::from_raw_os_error(errno).kind(),
+                None => io::ErrorKind::Other,
+            },
+        }
+    }
+
+    /// Recovers the semaphore from an [`Error::Sem`], or gives `self` back.
+    pub fn into_sem(self) -> std::result::Result<(Semaphore, Error), Error> {
+        match self {
+            Error::Sem(sem, err) => Ok((sem, *err)),
+            err => Err(err),
+        }
+    }
+
+    /// Recovers the queue from an [`Error::Mq`], or gives `self` back.
     #[cfg(not(target_os = "android"))]
-    pub fn into_mq(self) -> (MessageQueue, libc::c_int, String) {
+    pub fn into_mq(self) -> std::result::Result<(MessageQueue, Error), Error> {
         match self {
-            Error::Mq(mq, errno, msg) => (mq, errno, msg),
-            _ => panic!("can't into"),
+            Error::Mq(mq, err) => Ok((mq, *err)),
+            err => Err(err),
         }
     }
 }
 
-pub(crate) fn strerror(errno: i32) -> Result<String> {
-    unsafe {
-        let cstr = CStr::from_ptr(libc::strerror(errno as _));
-        Ok(cstr.to_str()?.to_string())
+impl From<Error> for io::Error {
+    /// Keeps the whole [`Error`] as the payload, so it can be recovered with
+    /// `get_ref()` and `downcast_ref::<Error>()`; [`Error::Io`] is unwrapped.
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::Io(err) => err,
+            err => io::Error::new(err.kind(), err),
+        }
     }
 }
 
@@ -74,32 +183,14 @@ pub(crate) fn libc_errno() -> libc::c_int {
     }
 }
 
-macro_rules! panic_errno {
-    ($msg: expr) => {{
-        let errno = $crate::errors::libc_errno();
-        panic!(
-            "{}: {}({})",
-            $msg,
-            $crate::errors::strerror(errno).unwrap(),
-            errno
-        )
-    }};
-}
-
 macro_rules! return_errno {
-    ($msg: expr) => {{
+    ($op: expr) => {{
         let errno = $crate::errors::libc_errno();
-        return Err($crate::Error::Errno(
-            errno,
-            format!("{}: {}", $msg, $crate::errors::strerror(errno)?),
-        ));
+        return Err($crate::Error::errno(errno, $op, None));
     }};
 
-    () => {
+    ($op: expr, $object: expr) => {{
         let errno = $crate::errors::libc_errno();
-        return Err($crate::Error::Errno(
-            errno,
-            $crate::errors::strerror(errno)?,
-        ));
-    };
+        return Err($crate::Error::errno(errno, $op, Some($object)));
+    }};
 }
@@ -0,0 +1,111 @@
+use crate::futex::{self, WaitResult};
+use crate::Result;
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+const UNSET: u32 = 0;
+const SET: u32 = 1;
+
+const MANUAL: u32 = 0;
+const AUTO: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetMode {
+    /// Stays set, releasing every waiter, until `reset` is called.
+    Manual,
+    /// Releases a single waiter and resets itself.
+    Auto,
+}
+
+/// A futex based event that can be shared between processes.
+///
+/// Place it in a [`Shm`](crate::shm::Shm) (named, or anonymous and inherited
+/// across `fork`) to signal readiness without a named kernel object.
+#[derive(Debug)]
+#[repr(C)]
+pub struct SharedEvent {
+    state: AtomicU32,
+    // 别的进程或残留的段可能写进任何值，存原始数字，用时再检查
+    mode: u32,
+}
+
+unsafe impl crate::shm::ShmSafe for SharedEvent {}
+
+impl SharedEvent {
+    pub const fn new(mode: ResetMode) -> SharedEvent {
+        SharedEvent {
+            state: AtomicU32::new(UNSET),
+            mode: match mode {
+                ResetMode::Manual => MANUAL,
+                ResetMode::Auto => AUTO,
+            },
+        }
+    }
+
+    /// Fails with `ErrorKind::InvalidData` if the shared memory holds no
+    /// valid mode.
+    pub fn mode(&self) -> Result<ResetMode> {
+        match self.mode {
+            MANUAL => Ok(ResetMode::Manual),
+            AUTO => Ok(ResetMode::Auto),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "invalid event mode").into()),
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.state.load(Ordering::Acquire) == SET
+    }
+
+    pub fn set(&self) -> Result<()> {
+        let count = match self.mode()? {
+            ResetMode::Manual => i32::MAX as u32,
+            ResetMode::Auto => 1,
+        };
+        if self.state.swap(SET, Ordering::Release) == SET {
+            return Ok(());
+        }
+        futex::futex_wake(futex::as_word(&self.state), count)?;
+        Ok(())
+    }
+
+    pub fn reset(&self) {
+        self.state.store(UNSET, Ordering::Release);
+    }
+
+    pub fn wait(&self) -> Result<()> {
+        while !self.try_acquire()? {
+            futex::futex_wait(futex::as_word(&self.state), UNSET)?;
+        }
+        Ok(())
+    }
+
+    /// Returns `false` if the event was not set before `timeout` elapsed.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.try_acquire()? {
+                return Ok(true);
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(false);
+            }
+            if let WaitResult::Timeout =
+                futex::futex_timed_wait(futex::as_word(&self.state), UNSET, left)?
+            {
+                return self.try_acquire();
+            }
+        }
+    }
+
+    fn try_acquire(&self) -> Result<bool> {
+        Ok(match self.mode()? {
+            ResetMode::Manual => self.is_set(),
+            ResetMode::Auto => self
+                .state
+                .compare_exchange(SET, UNSET, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok(),
+        })
+    }
+}
@@ -0,0 +1,131 @@
+use crate::errors::libc_errno;
+use crate::Result;
+use std::cmp;
+use std::io::{self, IoSlice, IoSliceMut};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd};
+
+/// Descriptor-level controls shared by every type that wraps a descriptor:
+/// pipe ends, FIFOs, and std's own files and sockets.
+pub trait FdExt: AsFd {
+    /// Sets or clears `O_NONBLOCK` on the open file. The flag is shared with
+    /// every duplicate of the descriptor, including those in other processes.
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        let fd = self.as_fd().as_raw_fd();
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags == -1 {
+                return_errno!("fcntl");
+            }
+            let new = if nonblocking {
+                flags | libc::O_NONBLOCK
+            } else {
+                flags & !libc::O_NONBLOCK
+            };
+            if new != flags && libc::fcntl(fd, libc::F_SETFL, new) == -1 {
+                return_errno!("fcntl");
+            }
+            Ok(())
+        }
+    }
+
+    /// Sets or clears `FD_CLOEXEC`, which only affects this descriptor.
+    fn set_cloexec(&self, cloexec: bool) -> Result<()> {
+        let fd = self.as_fd().as_raw_fd();
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFD);
+            if flags == -1 {
+                return_errno!("fcntl");
+            }
+            let new = if cloexec {
+                flags | libc::FD_CLOEXEC
+            } else {
+                flags & !libc::FD_CLOEXEC
+            };
+            if new != flags && libc::fcntl(fd, libc::F_SETFD, new) == -1 {
+                return_errno!("fcntl");
+            }
+            Ok(())
+        }
+    }
+
+    /// The number of bytes that can be read right now without blocking.
+    fn bytes_available(&self) -> Result<usize> {
+        let mut n: libc::c_int = 0;
+        unsafe {
+            if libc::ioctl(self.as_fd().as_raw_fd(), libc::FIONREAD, &mut n) == -1 {
+                return_errno!("ioctl");
+            }
+        }
+        Ok(n as _)
+    }
+}
+
+impl<T: AsFd + ?Sized> FdExt for T {}
+
+/// Duplicates `fd` with `F_DUPFD_CLOEXEC`. The duplicate shares the open
+/// file, offset and status flags, but is always close-on-exec.
+pub fn try_clone(fd: BorrowedFd<'_>) -> Result<OwnedFd> {
+    unsafe {
+        let new = libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0);
+        if new == -1 {
+            return_errno!("fcntl");
+        }
+        Ok(OwnedFd::from_raw_fd(new))
+    }
+}
+
+/// Closes `fd`, reporting the error that dropping an [`OwnedFd`] ignores.
+/// The descriptor is released even when `close` fails.
+pub fn close(fd: OwnedFd) -> Result<()> {
+    unsafe {
+        if libc::close(fd.into_raw_fd()) == -1 {
+            return_errno!("close");
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn read(fd: BorrowedFd<'_>, buf: &mut [u8]) -> io::Result<usize> {
+    unsafe {
+        let n = libc::read(fd.as_raw_fd(), buf.as_mut_ptr() as _, buf.len() as _);
+        if n == -1 {
+            return Err(io::Error::from_raw_os_error(libc_errno() as _));
+        }
+        Ok(n as _)
+    }
+}
+
+pub(crate) fn read_vectored(fd: BorrowedFd<'_>, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+    unsafe {
+        let n = libc::readv(fd.as_raw_fd(), bufs.as_ptr() as _, iov_count(bufs.len()));
+        if n == -1 {
+            return Err(io::Error::from_raw_os_error(libc_errno() as _));
+        }
+        Ok(n as _)
+    }
+}
+
+pub(crate) fn write(fd: BorrowedFd<'_>, buf: &[u8]) -> io::Result<usize> {
+    unsafe {
+        let n = libc::write(fd.as_raw_fd(), buf.as_ptr() as _, buf.len() as _);
+        if n == -1 {
+            return Err(io::Error::from_raw_os_error(libc_errno() as _));
+        }
+        Ok(n as _)
+    }
+}
+
+pub(crate) fn write_vectored(fd: BorrowedFd<'_>, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+    unsafe {
+        let n = libc::writev(fd.as_raw_fd(), bufs.as_ptr() as _, iov_count(bufs.len()));
+        if n == -1 {
+            return Err(io::Error::from_raw_os_error(libc_errno() as _));
+        }
+        Ok(n as _)
+    }
+}
+
+// readv/writev 一次最多接受 IOV_MAX 个 iovec，多余的留给下次调用
+fn iov_count(len: usize) -> libc::c_int {
+    cmp::min(len, libc::UIO_MAXIOV as usize) as _
+}
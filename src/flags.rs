@@ -1,13 +1,68 @@
+use bitflags::bitflags;
 
-pub const O_CREAT: isize = libc::O_CREAT as _;
-pub const O_EXCL: isize = libc::O_EXCL as _;
-pub const O_RDONLY: isize = libc::O_RDONLY as _;
-pub const O_WRONLY: isize = libc::O_WRONLY as _;
-pub const O_RDWR: isize = libc::O_RDWR as _;
-pub const O_NONBLOCK: isize = libc::O_NONBLOCK as _;
-pub const O_CLOEXEC: isize = libc::O_CLOEXEC as _;
-pub const O_DIRECT: isize = libc::O_DIRECT as _;
-
-pub const WNOHANG: isize = libc::WNOHANG as _;
-pub const WUNTRACED: isize = libc::WUNTRACED as _;
-pub const WCONTINUED: isize = libc::WCONTINUED as _;
+bitflags! {
+    /// Flags for opening POSIX named objects: message queues, semaphores and
+    /// FIFOs.
+    pub struct OpenFlags: libc::c_int {
+        const RDONLY = libc::O_RDONLY;
+        const WRONLY = libc::O_WRONLY;
+        const RDWR = libc::O_RDWR;
+        const CREAT = libc::O_CREAT;
+        const EXCL = libc::O_EXCL;
+        const NONBLOCK = libc::O_NONBLOCK;
+        const CLOEXEC = libc::O_CLOEXEC;
+    }
+}
+
+bitflags! {
+    /// Flags for creating pipes with `pipe2`.
+    pub struct PipeFlags: libc::c_int {
+        const NONBLOCK = libc::O_NONBLOCK;
+        const CLOEXEC = libc::O_CLOEXEC;
+        /// Packet mode; see [`packet_pipe`](crate::pipe::packet_pipe).
+        const DIRECT = libc::O_DIRECT;
+    }
+}
+
+bitflags! {
+    /// Options for `waitpid` and `waitid`.
+    pub struct WaitOptions: libc::c_int {
+        const NOHANG = libc::WNOHANG;
+        const UNTRACED = libc::WUNTRACED;
+        const CONTINUED = libc::WCONTINUED;
+    }
+}
+
+bitflags! {
+    /// Flags for creating System V objects with `msgget`, `semget` and `shmget`.
+    pub struct IpcFlags: libc::c_int {
+        const CREAT = libc::IPC_CREAT;
+        const EXCL = libc::IPC_EXCL;
+    }
+}
+
+bitflags! {
+    /// Flags for `splice`, `tee` and `vmsplice`.
+    pub struct SpliceFlags: libc::c_uint {
+        const MOVE = libc::SPLICE_F_MOVE;
+        const NONBLOCK = libc::SPLICE_F_NONBLOCK;
+        const MORE = libc::SPLICE_F_MORE;
+        const GIFT = libc::SPLICE_F_GIFT;
+    }
+}
+
+bitflags! {
+    /// Permission bits for newly created objects, before the umask applies.
+    /// `Mode::from_bits_truncate(0o666)` reads like the familiar octal form.
+    pub struct Mode: libc::mode_t {
+        const OWNER_READ = libc::S_IRUSR;
+        const OWNER_WRITE = libc::S_IWUSR;
+        const OWNER_EXEC = libc::S_IXUSR;
+        const GROUP_READ = libc::S_IRGRP;
+        const GROUP_WRITE = libc::S_IWGRP;
+        const GROUP_EXEC = libc::S_IXGRP;
+        const OTHER_READ = libc::S_IROTH;
+        const OTHER_WRITE = libc::S_IWOTH;
+        const OTHER_EXEC = libc::S_IXOTH;
+    }
+}
@@ -3,6 +3,7 @@
 use crate::{errors::libc_errno, Result};
 use libc::{c_int, timespec};
 use std::ptr;
+use std::sync::atomic::AtomicU32;
 use std::time::{Duration, Instant};
 
 unsafe fn syscall_futex(
@@ -112,6 +113,11 @@ pub fn futex_timed_wait(addr: &u32, val: u32, timeout: Duration) -> Result<WaitR
     }
 }
 
+pub fn as_word(atomic: &AtomicU32) -> &u32 {
+    // AtomicU32 与 u32 内存布局相同
+    unsafe { &*(atomic as *const AtomicU32 as *const u32) }
+}
+
 trait AsTimespec {
     fn as_timespec(&self) -> timespec;
     fn from_timespec(tm: &timespec) -> Self;
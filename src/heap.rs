@@ -0,0 +1,324 @@
+//! A lock-free allocator for variable-size blocks inside one shared segment.
+//!
+//! Blocks come in power-of-two size classes. Freed blocks go onto a
+//! per-class free list and are reused only for that class; fresh blocks are
+//! carved from the end of the used part of the segment. Memory is never
+//! returned to the segment or moved between classes.
+//!
+//! A block is named by its offset in the segment, which is the same in
+//! every process whatever address the segment is mapped at. Pass
+//! [`SharedPtr::into_offset`] through a ring or queue and rebuild the handle
+//! on the other side with [`SharedHeap::from_offset`].
+
+use crate::shm::{Shm, ShmSafe};
+use crate::Result;
+use std::sync::atomic::{self, AtomicU32, AtomicU64, Ordering};
+use std::{fmt, io, mem, slice};
+
+const MAGIC: u32 = 0x6865_6170;
+const CLASSES: usize = 32;
+const MIN_BLOCK: u64 = 32;
+
+// 空闲链表头：低 40 位是块偏移，高 24 位是防 ABA 的版本号
+const OFFSET_BITS: u32 = 40;
+const OFFSET_MASK: u64 = (1 << OFFSET_BITS) - 1;
+
+#[repr(C)]
+struct Header {
+    magic: u32,
+    _pad: u32,
+    size: u64,
+    next: AtomicU64,
+    free: [AtomicU64; CLASSES],
+}
+
+unsafe impl ShmSafe for Header {}
+
+#[repr(C)]
+struct BlockHeader {
+    refs: AtomicU32,
+    class: AtomicU32,
+    // 分配中是数据长度，空闲时是链表里下一个块的偏移
+    word: AtomicU64,
+}
+
+unsafe impl ShmSafe for BlockHeader {}
+
+const DATA_START: u64 = (mem::size_of::<Header>() as u64 + 63) & !63;
+const BLOCK_HEADER_SIZE: u64 = mem::size_of::<BlockHeader>() as u64;
+
+fn block_size(class: usize) -> u64 {
+    MIN_BLOCK << class
+}
+
+fn class_for(len: usize) -> Option<usize> {
+    let need = (len as u64).checked_add(BLOCK_HEADER_SIZE)?;
+    let class = need
+        .max(MIN_BLOCK)
+        .checked_next_power_of_two()?
+        .trailing_zeros()
+        - MIN_BLOCK.trailing_zeros();
+    Some(class as usize).filter(|&class| class < CLASSES)
+}
+
+fn check_size(size: usize) -> Result<()> {
+    if (size as u64) < DATA_START || size as u64 > OFFSET_MASK {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid heap size").into());
+    }
+    Ok(())
+}
+
+fn out_of_memory() -> crate::Error {
+    io::Error::new(io::ErrorKind::OutOfMemory, "shared heap exhausted").into()
+}
+
+/// Variable-size blocks in one shared segment, allocated and freed from any
+/// number of processes without locks.
+#[derive(Debug)]
+pub struct SharedHeap {
+    shm: Shm,
+}
+
+impl SharedHeap {
+    pub fn create(name: &str, size: usize) -> Result<SharedHeap> {
+        Self::init(Shm::open(name, size, true)?)
+    }
+
+    /// Opens a heap some process created with the same `size`.
+    pub fn open(name: &str, size: usize) -> Result<SharedHeap> {
+        check_size(size)?;
+        let heap = SharedHeap {
+            shm: Shm::open(name, size, false)?,
+        };
+        let header = heap.header();
+        if header.magic != MAGIC || header.size != size as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "heap layout mismatch").into());
+        }
+        Ok(heap)
+    }
+
+    /// Creates a heap in an anonymous mapping, shared with children created
+    /// by `fork` afterwards.
+    pub fn anonymous(size: usize) -> Result<SharedHeap> {
+        Self::init(Shm::anonymous(size)?)
+    }
+
+    fn init(shm: Shm) -> Result<SharedHeap> {
+        check_size(shm.len())?;
+        unsafe {
+            shm.place(
+                0,
+                Header {
+                    magic: MAGIC,
+                    _pad: 0,
+                    size: shm.len() as u64,
+                    next: AtomicU64::new(DATA_START),
+                    free: Default::default(),
+                },
+            )?;
+        }
+        Ok(SharedHeap { shm })
+    }
+
+    fn header(&self) -> &Header {
+        // 打开时已检查段至少有 DATA_START 字节
+        unsafe { &*self.shm.slot_unchecked::<Header>(0) }
+    }
+
+    /// The header of the block at `offset`, which must be a block this heap
+    /// carved: those all lie inside the segment at multiples of `MIN_BLOCK`.
+    fn block(&self, offset: u64) -> &BlockHeader {
+        unsafe { &*self.shm.slot_unchecked::<BlockHeader>(offset as usize) }
+    }
+
+    pub fn size(&self) -> usize {
+        self.shm.len()
+    }
+
+    /// Bytes carved into blocks so far, free or not.
+    pub fn used(&self) -> usize {
+        (self.header().next.load(Ordering::Relaxed) - DATA_START) as usize
+    }
+
+    /// Allocates `len` bytes, zeroed if the block is fresh and holding stale
+    /// data if it is reused. Fails with `ErrorKind::OutOfMemory` once no
+    /// block of the right class is free and the segment is used up.
+    pub fn alloc(&self, len: usize) -> Result<SharedPtr<'_>> {
+        let class = class_for(len).ok_or_else(out_of_memory)?;
+        let offset = match self.pop(class) {
+            Some(offset) => offset,
+            None => self.carve(class)?,
+        };
+        let block = self.block(offset);
+        block.word.store(len as u64, Ordering::Relaxed);
+        block.refs.store(1, Ordering::Release);
+        Ok(SharedPtr { heap: self, offset })
+    }
+
+    /// Rebuilds a handle from [`SharedPtr::into_offset`], taking over the
+    /// reference that call gave up.
+    ///
+    /// # Safety
+    ///
+    /// `offset` must come from `into_offset` on this heap, and each such
+    /// offset may be passed here only once.
+    pub unsafe fn from_offset(&self, offset: u64) -> Result<SharedPtr<'_>> {
+        // 只有已切出的块才可能是合法偏移，它们都按 MIN_BLOCK 对齐
+        let carved = self.header().next.load(Ordering::Relaxed);
+        if offset < DATA_START || offset >= carved || offset % MIN_BLOCK != 0 {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidInput, "not a heap block offset").into(),
+            );
+        }
+        Ok(SharedPtr { heap: self, offset })
+    }
+
+    fn carve(&self, class: usize) -> Result<u64> {
+        let size = block_size(class);
+        let header = self.header();
+        let mut next = header.next.load(Ordering::Relaxed);
+        loop {
+            let end = next.checked_add(size).ok_or_else(out_of_memory)?;
+            if end > header.size {
+                return Err(out_of_memory());
+            }
+            match header
+                .next
+                .compare_exchange_weak(next, end, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(current) => next = current,
+            }
+        }
+        self.block(next)
+            .class
+            .store(class as u32, Ordering::Relaxed);
+        Ok(next)
+    }
+
+    fn pop(&self, class: usize) -> Option<u64> {
+        let list = &self.header().free[class];
+        let mut head = list.load(Ordering::Acquire);
+        loop {
+            let offset = head & OFFSET_MASK;
+            if offset == 0 {
+                return None;
+            }
+            // 块可能已被别的进程取走重用，读到的 next 作废时版本号会让 CAS 失败
+            let next = self.block(offset).word.load(Ordering::Relaxed) & OFFSET_MASK;
+            let new = tagged(head, next);
+            match list.compare_exchange_weak(head, new, Ordering::Acquire, Ordering::Acquire) {
+                Ok(_) => return Some(offset),
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    fn push(&self, offset: u64) {
+        let block = self.block(offset);
+        let list = &self.header().free[block.class.load(Ordering::Relaxed) as usize];
+        let mut head = list.load(Ordering::Relaxed);
+        loop {
+            block.word.store(head & OFFSET_MASK, Ordering::Relaxed);
+            let new = tagged(head, offset);
+            match list.compare_exchange_weak(head, new, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+fn tagged(head: u64, offset: u64) -> u64 {
+    let tag = (head >> OFFSET_BITS).wrapping_add(1);
+    (tag << OFFSET_BITS) | offset
+}
+
+/// A counted reference to a block in a [`SharedHeap`]. The block is freed
+/// when the last reference, in any process, is dropped.
+pub struct SharedPtr<'a> {
+    heap: &'a SharedHeap,
+    offset: u64,
+}
+
+impl SharedPtr<'_> {
+    fn block(&self) -> &BlockHeader {
+        self.heap.block(self.offset)
+    }
+
+    /// The block's offset in the heap, valid in every process.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Gives up this reference without releasing it, for another process
+    /// to take over with [`SharedHeap::from_offset`].
+    pub fn into_offset(self) -> u64 {
+        let offset = self.offset;
+        mem::forget(self);
+        offset
+    }
+
+    pub fn len(&self) -> usize {
+        self.block().word.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of references to the block across all processes.
+    pub fn ref_count(&self) -> u32 {
+        self.block().refs.load(Ordering::Acquire)
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        unsafe {
+            self.heap
+                .shm
+                .as_ptr()
+                .add((self.offset + BLOCK_HEADER_SIZE) as usize) as *mut u8
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.len()) }
+    }
+
+    /// Returns the block's bytes for writing if this is its only reference.
+    pub fn get_mut(&mut self) -> Option<&mut [u8]> {
+        if self.ref_count() != 1 {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts_mut(self.as_ptr(), self.len()) })
+    }
+}
+
+impl Clone for SharedPtr<'_> {
+    fn clone(&self) -> Self {
+        self.block().refs.fetch_add(1, Ordering::Relaxed);
+        SharedPtr {
+            heap: self.heap,
+            offset: self.offset,
+        }
+    }
+}
+
+impl Drop for SharedPtr<'_> {
+    fn drop(&mut self) {
+        if self.block().refs.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        atomic::fence(Ordering::Acquire);
+        self.heap.push(self.offset);
+    }
+}
+
+impl fmt::Debug for SharedPtr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedPtr")
+            .field("offset", &self.offset)
+            .field("len", &self.len())
+            .finish()
+    }
+}
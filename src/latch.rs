@@ -0,0 +1,67 @@
+use crate::futex::{self, WaitResult};
+use crate::Result;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+/// A futex based countdown latch that can be shared between processes.
+///
+/// Waiters are released once `count_down` has been called `count` times.
+#[derive(Debug)]
+#[repr(C)]
+pub struct CountDownLatch {
+    count: AtomicU32,
+}
+
+unsafe impl crate::shm::ShmSafe for CountDownLatch {}
+
+impl CountDownLatch {
+    pub const fn new(count: u32) -> CountDownLatch {
+        CountDownLatch {
+            count: AtomicU32::new(count),
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count.load(Ordering::Acquire)
+    }
+
+    pub fn count_down(&self) -> Result<()> {
+        let prev = self
+            .count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |c| c.checked_sub(1));
+        if prev == Ok(1) {
+            futex::futex_wake(futex::as_word(&self.count), i32::MAX as u32)?;
+        }
+        Ok(())
+    }
+
+    pub fn wait(&self) -> Result<()> {
+        loop {
+            let count = self.count();
+            if count == 0 {
+                return Ok(());
+            }
+            futex::futex_wait(futex::as_word(&self.count), count)?;
+        }
+    }
+
+    /// Returns `false` if the count did not reach zero before `timeout` elapsed.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let count = self.count();
+            if count == 0 {
+                return Ok(true);
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(false);
+            }
+            if let WaitResult::Timeout =
+                futex::futex_timed_wait(futex::as_word(&self.count), count, left)?
+            {
+                return Ok(self.count() == 0);
+            }
+        }
+    }
+}
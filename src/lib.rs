@@ -5,30 +5,50 @@ use cfg_if::cfg_if;
 #[macro_use]
 mod errors;
 
+#[cfg(feature = "serde")]
+pub mod channel;
+pub mod fd;
 pub mod flags;
 pub(crate) mod futex;
+pub mod marshal;
 pub mod pipe;
-pub(crate) mod raw;
+pub mod process;
+pub mod rpc;
 pub mod sem;
+pub mod socket;
+pub mod transport;
 
 cfg_if! {
     if #[cfg(not(target_os = "android"))] {
+        pub mod bus;
+        pub mod event;
+        pub mod heap;
+        pub mod latch;
+        pub mod map;
         pub mod mq;
+        pub mod pool;
         pub mod ring;
-        pub(crate) mod shm;
+        pub mod seqlock;
+        pub mod shm;
+        pub mod sysv;
     }
 }
 
+#[cfg(feature = "serde")]
+pub use channel::channel;
 pub use errors::Error;
 pub type Result<T> = std::result::Result<T, Error>;
 
-pub fn fork() -> Result<i32> {
+pub fn fork() -> Result<process::Fork> {
     unsafe {
         let pid = libc::fork();
         if pid == -1 {
             return_errno!("fork");
         }
-        Ok(pid)
+        if pid == 0 {
+            return Ok(process::Fork::Child);
+        }
+        Ok(process::Fork::Parent(process::Child::from_pid(pid)))
     }
 }
 
@@ -40,13 +60,21 @@ pub fn getppid() -> i32 {
     unsafe { libc::getppid() }
 }
 
-pub fn waitpid(pid: i32, options: isize) -> Result<(i32, isize)> {
+/// Returns the pid and new state of a child matching `pid`, or `None` with
+/// `NOHANG` when no such child has changed state yet.
+pub fn waitpid(
+    pid: i32,
+    options: flags::WaitOptions,
+) -> Result<Option<(i32, process::ExitStatus)>> {
     unsafe {
         let mut status: libc::c_int = 0;
-        let ret = libc::waitpid(pid as _, &mut status, options as _);
+        let ret = libc::waitpid(pid as _, &mut status, options.bits());
         if ret == -1 {
             return_errno!("waitpid");
         }
-        Ok((ret, status as _))
+        if ret == 0 {
+            return Ok(None);
+        }
+        Ok(Some((ret, process::ExitStatus::from_raw(status))))
     }
 }
@@ -0,0 +1,362 @@
+//! A fixed-capacity hash map stored entirely in a shared segment.
+//!
+//! Keys and values are plain fixed-size data. Writers take a lock in the
+//! segment and update one bucket at a time under that bucket's sequence
+//! counter; readers never lock, and retry a bucket they caught mid-write.
+//! Every lookup sees each bucket either before or after a write, but a scan
+//! over several buckets is not a snapshot of the whole map.
+//!
+//! The writer lock is an [`UnnamedSemaphore`] that nothing releases on a
+//! process's behalf: a writer that dies while holding it deadlocks every
+//! later writer, and one that dies mid-write leaves readers of that bucket
+//! spinning.
+
+use crate::sem::{SemaphoreLike, UnnamedSemaphore};
+use crate::shm::{Shm, ShmSafe};
+use crate::Result;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem::{self, MaybeUninit};
+use std::sync::atomic::{self, AtomicU32, Ordering};
+use std::{fmt, io, ptr};
+
+const MAGIC: u32 = 0x6d61_7021;
+// 记在头部里，换了哈希函数的程序打开旧的表会直接失败
+const HASH_FNV1A: u32 = 1;
+
+const EMPTY: u32 = 0;
+const FULL: u32 = 1;
+const DELETED: u32 = 2;
+
+#[repr(C)]
+struct Header {
+    magic: u32,
+    capacity: u32,
+    key_size: u32,
+    value_size: u32,
+    hash: u32,
+    len: AtomicU32,
+}
+
+unsafe impl ShmSafe for Header {}
+
+#[repr(C)]
+struct Bucket<K, V> {
+    // 奇数表示正在写
+    seq: AtomicU32,
+    state: AtomicU32,
+    key: MaybeUninit<K>,
+    value: MaybeUninit<V>,
+}
+
+enum Slot<K, V> {
+    Empty,
+    Deleted,
+    Full(K, V),
+}
+
+const LOCK_OFFSET: usize = (mem::size_of::<Header>() + 63) & !63;
+const BUCKETS_OFFSET: usize = (LOCK_OFFSET + mem::size_of::<UnnamedSemaphore>() + 63) & !63;
+
+/// A hash map in shared memory, created by one process and opened by name
+/// from others.
+///
+/// Keys are placed by FNV-1a over their `Hash` impl, so separately built
+/// programs can share a map as long as their key types hash alike. Removed
+/// entries leave tombstones that only insertions reclaim.
+pub struct SharedMap<K, V> {
+    shm: Shm,
+    capacity: usize,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> SharedMap<K, V>
+where
+    K: ShmSafe + Copy + Eq + Hash,
+    V: ShmSafe + Copy,
+{
+    pub fn create(name: &str, capacity: usize) -> Result<SharedMap<K, V>> {
+        if capacity == 0 || capacity > u32::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid map capacity").into());
+        }
+        let shm = Shm::open(name, Self::total_size(capacity)?, true)?;
+        unsafe {
+            UnnamedSemaphore::init_in(&shm, LOCK_OFFSET, 1)?;
+            // 头部最后写入，open 看到 MAGIC 即说明锁已初始化
+            shm.place(
+                0,
+                Header {
+                    magic: MAGIC,
+                    capacity: capacity as _,
+                    key_size: mem::size_of::<K>() as _,
+                    value_size: mem::size_of::<V>() as _,
+                    hash: HASH_FNV1A,
+                    len: AtomicU32::new(0),
+                },
+            )?;
+        }
+        Ok(SharedMap {
+            shm,
+            capacity,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Opens a map some process created with the same capacity and types.
+    pub fn open(name: &str, capacity: usize) -> Result<SharedMap<K, V>> {
+        let shm = Shm::open(name, Self::total_size(capacity)?, false)?;
+        let header = unsafe { shm.attach::<Header>(0)? };
+        if header.magic != MAGIC
+            || header.capacity as usize != capacity
+            || header.key_size as usize != mem::size_of::<K>()
+            || header.value_size as usize != mem::size_of::<V>()
+        {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "map layout mismatch").into());
+        }
+        if header.hash != HASH_FNV1A {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "map hash mismatch").into());
+        }
+        Ok(SharedMap {
+            shm,
+            capacity,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.header().len.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        for index in self.probe(key) {
+            match self.read(index) {
+                Slot::Empty => return None,
+                Slot::Full(k, v) if k == *key => return Some(v),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts or replaces the value under `key` and returns the old one.
+    /// Fails with `ErrorKind::OutOfMemory` if the key is new and every
+    /// bucket is taken.
+    pub fn insert(&self, key: K, value: V) -> Result<Option<V>> {
+        let _lock = self.lock()?.acquire()?;
+        self.insert_locked(key, value)
+    }
+
+    /// Replaces the value under `key` with `f` of the current one, as one
+    /// write with respect to other writers.
+    pub fn update<F>(&self, key: K, f: F) -> Result<V>
+    where
+        F: FnOnce(Option<V>) -> V,
+    {
+        let _lock = self.lock()?.acquire()?;
+        let value = f(self.get(&key));
+        self.insert_locked(key, value)?;
+        Ok(value)
+    }
+
+    pub fn remove(&self, key: &K) -> Result<Option<V>> {
+        let _lock = self.lock()?.acquire()?;
+        for index in self.probe(key) {
+            match self.read(index) {
+                Slot::Empty => return Ok(None),
+                Slot::Full(k, v) if k == *key => {
+                    self.write(index, DELETED, None);
+                    self.header().len.fetch_sub(1, Ordering::Relaxed);
+                    return Ok(Some(v));
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Iterates over the entries, reading each bucket consistently.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            map: self,
+            index: 0,
+        }
+    }
+
+    /// Closes the map and, if this process created it, unlinks it,
+    /// reporting the errors that dropping would only log.
+    pub fn close(mut self) -> Result<()> {
+        // 其他进程可能还开着，锁不 destroy，只 unmap 和 unlink
+        self.shm.release()
+    }
+
+    fn insert_locked(&self, key: K, value: V) -> Result<Option<V>> {
+        let mut vacant = None;
+        for index in self.probe(&key) {
+            match self.read(index) {
+                Slot::Full(k, old) if k == key => {
+                    self.write(index, FULL, Some((key, value)));
+                    return Ok(Some(old));
+                }
+                Slot::Full(..) => {}
+                Slot::Deleted => {
+                    vacant.get_or_insert(index);
+                }
+                Slot::Empty => {
+                    vacant.get_or_insert(index);
+                    break;
+                }
+            }
+        }
+        let index =
+            vacant.ok_or_else(|| io::Error::new(io::ErrorKind::OutOfMemory, "shared map full"))?;
+        self.write(index, FULL, Some((key, value)));
+        self.header().len.fetch_add(1, Ordering::Relaxed);
+        Ok(None)
+    }
+
+    fn header(&self) -> &Header {
+        // 段至少有 BUCKETS_OFFSET 字节，头部总在界内
+        unsafe { &*self.shm.slot_unchecked::<Header>(0) }
+    }
+
+    fn lock(&self) -> Result<&UnnamedSemaphore> {
+        unsafe { Ok(UnnamedSemaphore::attach(&self.shm, LOCK_OFFSET)?.get_ref()) }
+    }
+
+    /// The bucket at `index`, which must be below the capacity: the segment
+    /// was sized by `total_size` to hold that many aligned buckets.
+    fn bucket(&self, index: usize) -> *mut Bucket<K, V> {
+        debug_assert!(index < self.capacity);
+        let offset = BUCKETS_OFFSET + index * mem::size_of::<Bucket<K, V>>();
+        unsafe { self.shm.slot_unchecked::<Bucket<K, V>>(offset) }
+    }
+
+    fn probe(&self, key: &K) -> impl Iterator<Item = usize> {
+        let mut hasher = Fnv1a::default();
+        key.hash(&mut hasher);
+        let start = (hasher.finish() % self.capacity as u64) as usize;
+        let capacity = self.capacity;
+        (0..capacity).map(move |i| (start + i) % capacity)
+    }
+
+    fn read(&self, index: usize) -> Slot<K, V> {
+        let bucket = self.bucket(index);
+        loop {
+            unsafe {
+                let seq = (*bucket).seq.load(Ordering::Acquire);
+                if seq % 2 == 1 {
+                    std::hint::spin_loop();
+                    continue;
+                }
+                let state = (*bucket).state.load(Ordering::Relaxed);
+                let key = ptr::read_volatile(ptr::addr_of!((*bucket).key));
+                let value = ptr::read_volatile(ptr::addr_of!((*bucket).value));
+                atomic::fence(Ordering::Acquire);
+                if (*bucket).seq.load(Ordering::Relaxed) != seq {
+                    continue;
+                }
+                return match state {
+                    FULL => Slot::Full(key.assume_init(), value.assume_init()),
+                    EMPTY => Slot::Empty,
+                    _ => Slot::Deleted,
+                };
+            }
+        }
+    }
+
+    /// Writes one bucket. Callers hold the writer lock.
+    fn write(&self, index: usize, state: u32, entry: Option<(K, V)>) {
+        let bucket = self.bucket(index);
+        unsafe {
+            (*bucket).seq.fetch_add(1, Ordering::Relaxed);
+            atomic::fence(Ordering::Release);
+            (*bucket).state.store(state, Ordering::Relaxed);
+            if let Some((key, value)) = entry {
+                ptr::write_volatile(ptr::addr_of_mut!((*bucket).key), MaybeUninit::new(key));
+                ptr::write_volatile(ptr::addr_of_mut!((*bucket).value), MaybeUninit::new(value));
+            }
+            (*bucket).seq.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    fn total_size(capacity: usize) -> Result<usize> {
+        // 桶从 64 字节对齐的偏移开始，对齐要求更高的键值放不进来
+        if mem::align_of::<Bucket<K, V>>() > 64 {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidInput, "map entry over-aligned").into(),
+            );
+        }
+        capacity
+            .checked_mul(mem::size_of::<Bucket<K, V>>())
+            .and_then(|buckets| buckets.checked_add(BUCKETS_OFFSET))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "map too large").into())
+    }
+}
+
+/// 64-bit FNV-1a. Bucket placement must agree across every process and
+/// build that opens the map, so it can't use std's unspecified hasher.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl<K, V> fmt::Debug for SharedMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedMap")
+            .field("name", &self.shm.name())
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    map: &'a SharedMap<K, V>,
+    index: usize,
+}
+
+impl<K, V> Iterator for Iter<'_, K, V>
+where
+    K: ShmSafe + Copy + Eq + Hash,
+    V: ShmSafe + Copy,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        while self.index < self.map.capacity {
+            let index = self.index;
+            self.index += 1;
+            if let Slot::Full(key, value) = self.map.read(index) {
+                return Some((key, value));
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,191 @@
+use crate::Result;
+use std::io;
+
+/// Types that can be flattened into bytes and rebuilt in another process.
+///
+/// Values are encoded in native byte order, so both sides must run the same
+/// build on the same machine, as a forked child does.
+pub trait Marshal: Sized {
+    fn marshal(&self, buf: &mut Vec<u8>);
+
+    /// Rebuilds a value from the front of `bytes` and advances past it.
+    fn unmarshal(bytes: &mut &[u8]) -> Result<Self>;
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if bytes.len() < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated value").into());
+    }
+    let (head, tail) = bytes.split_at(len);
+    *bytes = tail;
+    Ok(head)
+}
+
+fn invalid(msg: &'static str) -> crate::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg).into()
+}
+
+macro_rules! marshal_number {
+    ($($t: ty),*) => {
+        $(
+            impl Marshal for $t {
+                fn marshal(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_ne_bytes());
+                }
+
+                fn unmarshal(bytes: &mut &[u8]) -> Result<Self> {
+                    let raw = take(bytes, std::mem::size_of::<$t>())?;
+                    Ok(<$t>::from_ne_bytes(raw.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+marshal_number!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+impl Marshal for () {
+    fn marshal(&self, _buf: &mut Vec<u8>) {}
+
+    fn unmarshal(_bytes: &mut &[u8]) -> Result<Self> {
+        Ok(())
+    }
+}
+
+impl Marshal for bool {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+
+    fn unmarshal(bytes: &mut &[u8]) -> Result<Self> {
+        match take(bytes, 1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("invalid bool")),
+        }
+    }
+}
+
+impl Marshal for String {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        self.len().marshal(buf);
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn unmarshal(bytes: &mut &[u8]) -> Result<Self> {
+        let len = usize::unmarshal(bytes)?;
+        Ok(std::str::from_utf8(take(bytes, len)?)?.to_string())
+    }
+}
+
+impl<T: Marshal> Marshal for Vec<T> {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        self.len().marshal(buf);
+        for item in self {
+            item.marshal(buf);
+        }
+    }
+
+    fn unmarshal(bytes: &mut &[u8]) -> Result<Self> {
+        let len = usize::unmarshal(bytes)?;
+        // 长度来自对端，不据此预分配
+        let mut items = Vec::new();
+        for _ in 0..len {
+            items.push(T::unmarshal(bytes)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Marshal> Marshal for Option<T> {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        self.is_some().marshal(buf);
+        if let Some(value) = self {
+            value.marshal(buf);
+        }
+    }
+
+    fn unmarshal(bytes: &mut &[u8]) -> Result<Self> {
+        if bool::unmarshal(bytes)? {
+            Ok(Some(T::unmarshal(bytes)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T: Marshal, E: Marshal> Marshal for std::result::Result<T, E> {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        self.is_ok().marshal(buf);
+        match self {
+            Ok(value) => value.marshal(buf),
+            Err(err) => err.marshal(buf),
+        }
+    }
+
+    fn unmarshal(bytes: &mut &[u8]) -> Result<Self> {
+        if bool::unmarshal(bytes)? {
+            Ok(Ok(T::unmarshal(bytes)?))
+        } else {
+            Ok(Err(E::unmarshal(bytes)?))
+        }
+    }
+}
+
+impl<A: Marshal, B: Marshal> Marshal for (A, B) {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        self.0.marshal(buf);
+        self.1.marshal(buf);
+    }
+
+    fn unmarshal(bytes: &mut &[u8]) -> Result<Self> {
+        Ok((A::unmarshal(bytes)?, B::unmarshal(bytes)?))
+    }
+}
+
+impl<A: Marshal, B: Marshal, C: Marshal> Marshal for (A, B, C) {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        self.0.marshal(buf);
+        self.1.marshal(buf);
+        self.2.marshal(buf);
+    }
+
+    fn unmarshal(bytes: &mut &[u8]) -> Result<Self> {
+        Ok((
+            A::unmarshal(bytes)?,
+            B::unmarshal(bytes)?,
+            C::unmarshal(bytes)?,
+        ))
+    }
+}
+
+/// Carries a serde value through [`Marshal`], encoded with bincode exactly
+/// like [`channel`](crate::channel) values, e.g. as a typed RPC request or a
+/// [`run_in_child`](crate::process::run_in_child) result.
+///
+/// # Panics
+///
+/// `marshal` panics if the value's `Serialize` impl fails, which bincode
+/// only does for sequences and maps of unknown length.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Serde<T>(pub T);
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> Marshal for Serde<T> {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        use bincode::Options;
+        crate::channel::options(usize::MAX)
+            .serialize_into(buf, &self.0)
+            .expect("bincode can't serialize the value");
+    }
+
+    fn unmarshal(bytes: &mut &[u8]) -> Result<Self> {
+        use bincode::Options;
+        // bincode 从切片读时会把它往前推，正好跳过这个值
+        crate::channel::options(bytes.len())
+            .deserialize_from(&mut *bytes)
+            .map(Serde)
+            .map_err(crate::Error::Decode)
+    }
+}
@@ -1,39 +1,58 @@
+use crate::flags::{Mode, OpenFlags};
 use crate::{errors::libc_errno, Error, Result};
+use log::error;
 use std::ffi::CString;
 use std::fmt::{Debug, Formatter};
-use std::mem::MaybeUninit;
-use std::{io, mem, ptr};
+use std::{cmp, io, mem, ptr};
 
 pub struct MessageQueue {
     inner: libc::mqd_t,
     name: String,
+    // 分散/聚集读写时拼接消息用，避免每次调用都分配
+    buf: Vec<u8>,
 }
 
 impl MessageQueue {
-    pub fn open(name: &str, flags: isize, mode: isize) -> Result<MessageQueue> {
+    pub fn open(name: &str, flags: OpenFlags, mode: Mode) -> Result<MessageQueue> {
+        Self::open_raw(name, flags, mode, ptr::null())
+    }
+
+    /// Like [`open`](Self::open), but a queue created by this call gets the
+    /// capacity and message size in `attr` instead of the system defaults.
+    pub fn open_with_attributes(
+        name: &str,
+        flags: OpenFlags,
+        mode: Mode,
+        attr: &MQAttribute,
+    ) -> Result<MessageQueue> {
+        Self::open_raw(name, flags, mode, &attr.0)
+    }
+
+    fn open_raw(
+        name: &str,
+        flags: OpenFlags,
+        mode: Mode,
+        attr: *const libc::mq_attr,
+    ) -> Result<MessageQueue> {
         unsafe {
             let c_name = CString::new(name)?;
-            let fd = libc::mq_open(
-                c_name.as_ptr(),
-                flags as _,
-                mode as libc::mode_t,
-                ptr::null::<libc::mq_attr>(),
-            );
+            let fd = libc::mq_open(c_name.as_ptr(), flags.bits(), mode.bits(), attr);
             if fd == -1 {
-                return_errno!("mq_open");
+                return_errno!("mq_open", name);
             }
             Ok(MessageQueue {
                 inner: fd,
                 name: name.to_string(),
+                buf: Vec::new(),
             })
         }
     }
 
     pub fn attributes(&self) -> Result<MQAttribute> {
         unsafe {
-            let mut attr: libc::mq_attr = MaybeUninit::uninit().assume_init();
+            let mut attr: libc::mq_attr = mem::zeroed();
             if libc::mq_getattr(self.inner, &mut attr) == -1 {
-                return_errno!("mq_getattr");
+                return_errno!("mq_getattr", &self.name);
             }
             Ok(MQAttribute(attr))
         }
@@ -42,7 +61,7 @@ impl MessageQueue {
     pub fn set_attributes(&mut self, attr: &MQAttribute) -> Result<()> {
         unsafe {
             if libc::mq_setattr(self.inner, &attr.0, ptr::null_mut()) == -1 {
-                return_errno!("mq_setattr");
+                return_errno!("mq_setattr", &self.name);
             }
             Ok(())
         }
@@ -51,18 +70,33 @@ impl MessageQueue {
     pub fn unlink_self(self) -> Result<()> {
         match Self::unlink(&self.name) {
             Ok(_) => Ok(()),
-            Err(err) => {
-                let (errno, msg) = err.into_errno();
-                Err(Error::Mq(self, errno, msg))
+            Err(err) => Err(Error::Mq(self, Box::new(err))),
+        }
+    }
+
+    /// Closes the queue, reporting the error that dropping would only log.
+    pub fn close(mut self) -> Result<()> {
+        self.release()
+    }
+
+    fn release(&mut self) -> Result<()> {
+        if self.inner == -1 {
+            return Ok(());
+        }
+        let mq = mem::replace(&mut self.inner, -1);
+        unsafe {
+            if libc::mq_close(mq) == -1 {
+                return_errno!("mq_close", &self.name);
             }
         }
+        Ok(())
     }
 
     pub fn unlink(name: &str) -> Result<()> {
         unsafe {
             let c_name = CString::new(name)?;
             if libc::mq_unlink(c_name.as_ptr()) == -1 {
-                return_errno!("mq_unlink");
+                return_errno!("mq_unlink", name);
             }
             Ok(())
         }
@@ -71,13 +105,14 @@ impl MessageQueue {
 
 impl Drop for MessageQueue {
     fn drop(&mut self) {
-        unsafe {
-            assert_ne!(libc::mq_close(self.inner), -1);
+        if let Err(err) = self.release() {
+            error!("{}", err);
         }
     }
 }
 
 impl io::Write for MessageQueue {
+    /// Sends `buf` as one message.
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         unsafe {
             let size = buf.len();
@@ -89,6 +124,21 @@ impl io::Write for MessageQueue {
         }
     }
 
+    /// Gathers `bufs` into a single message.
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        if let [buf] = bufs {
+            return self.write(buf);
+        }
+        let mut msg = mem::take(&mut self.buf);
+        msg.clear();
+        for buf in bufs {
+            msg.extend_from_slice(buf);
+        }
+        let ret = self.write(&msg);
+        self.buf = msg;
+        ret
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         // nothing to do
         Ok(())
@@ -110,6 +160,27 @@ impl io::Read for MessageQueue {
             Ok(n as _)
         }
     }
+
+    /// Receives one message and scatters it over `bufs`, whose total length
+    /// must be at least the queue's message size.
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        if let [buf] = bufs {
+            return self.read(buf);
+        }
+        let mut msg = mem::take(&mut self.buf);
+        msg.resize(bufs.iter().map(|buf| buf.len()).sum(), 0);
+        let ret = self.read(&mut msg);
+        if let Ok(n) = ret {
+            let mut rest = &msg[..n];
+            for buf in bufs.iter_mut() {
+                let len = cmp::min(buf.len(), rest.len());
+                buf[..len].copy_from_slice(&rest[..len]);
+                rest = &rest[len..];
+            }
+        }
+        self.buf = msg;
+        ret
+    }
 }
 
 impl Debug for MessageQueue {
@@ -128,8 +199,9 @@ impl MQAttribute {
         unsafe { MQAttribute(mem::zeroed()) }
     }
 
-    pub fn set_flags(&mut self, flags: isize) -> &mut Self {
-        self.0.mq_flags = flags as _;
+    /// Only [`OpenFlags::NONBLOCK`] can be changed on an open queue.
+    pub fn set_flags(&mut self, flags: OpenFlags) -> &mut Self {
+        self.0.mq_flags = flags.bits() as _;
         self
     }
 
@@ -143,8 +215,8 @@ impl MQAttribute {
         self
     }
 
-    pub fn flags(&self) -> isize {
-        self.0.mq_flags as _
+    pub fn flags(&self) -> OpenFlags {
+        OpenFlags::from_bits_truncate(self.0.mq_flags as _)
     }
 
     pub fn max_message_count(&self) -> isize {
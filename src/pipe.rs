@@ -1,27 +1,244 @@
-use crate::raw::RawFd;
-use crate::Result;
+use crate::errors::libc_errno;
+use crate::flags::{Mode, OpenFlags, PipeFlags, SpliceFlags};
+use crate::{fd, Error, Result};
+use log::error;
 use std::ffi::CString;
-use std::{fs, io};
+use std::io::{IoSlice, IoSliceMut};
+use std::os::unix::io::{
+    self as unix_io, AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{fs, io, ptr};
 
-pub struct PipeReader(RawFd);
-pub struct PipeWriter(RawFd);
+/// Writes of at most this many bytes are atomic: they are never interleaved
+/// with writes from other writers, and in packet mode form a single packet.
+pub const PIPE_BUF: usize = libc::PIPE_BUF;
+
+const PIPE_MAX_SIZE: &str = "/proc/sys/fs/pipe-max-size";
+
+#[derive(Debug)]
+pub struct PipeReader(OwnedFd);
+#[derive(Debug)]
+pub struct PipeWriter(OwnedFd);
 
 impl io::Read for PipeReader {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.0.read(buf)
+        fd::read(self.as_fd(), buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        fd::read_vectored(self.as_fd(), bufs)
     }
 }
 
 impl io::Write for PipeWriter {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.0.write(buf)
+        fd::write(self.as_fd(), buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        fd::write_vectored(self.as_fd(), bufs)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.0.flush()
+        Ok(())
+    }
+}
+
+impl PipeReader {
+    /// Duplicates this end; see [`fd::try_clone`].
+    pub fn try_clone(&self) -> Result<PipeReader> {
+        Ok(PipeReader(fd::try_clone(self.as_fd())?))
+    }
+
+    pub fn close(self) -> Result<()> {
+        fd::close(self.0)
+    }
+
+    /// Moves up to `len` bytes from the pipe to `fd` without copying them
+    /// through user space. `fd` may be a file, a socket or another pipe.
+    pub fn splice_to(
+        &mut self,
+        fd: &impl AsRawFd,
+        len: usize,
+        flags: SpliceFlags,
+    ) -> Result<usize> {
+        splice(self.as_raw_fd(), fd.as_raw_fd(), len, flags)
+    }
+
+    /// Copies up to `len` bytes from this pipe into `to` without consuming
+    /// them, so they can still be read from this pipe afterwards.
+    pub fn tee(&self, to: &PipeWriter, len: usize, flags: SpliceFlags) -> Result<usize> {
+        unsafe {
+            let n = libc::tee(self.as_raw_fd(), to.as_raw_fd(), len, flags.bits());
+            if n == -1 {
+                return_errno!("tee");
+            }
+            Ok(n as _)
+        }
+    }
+}
+
+impl PipeWriter {
+    /// Duplicates this end; see [`fd::try_clone`].
+    pub fn try_clone(&self) -> Result<PipeWriter> {
+        Ok(PipeWriter(fd::try_clone(self.as_fd())?))
+    }
+
+    pub fn close(self) -> Result<()> {
+        fd::close(self.0)
+    }
+
+    /// Moves up to `len` bytes from `fd` into the pipe without copying them
+    /// through user space.
+    pub fn splice_from(
+        &mut self,
+        fd: &impl AsRawFd,
+        len: usize,
+        flags: SpliceFlags,
+    ) -> Result<usize> {
+        splice(fd.as_raw_fd(), self.as_raw_fd(), len, flags)
+    }
+
+    pub fn capacity(&self) -> Result<usize> {
+        unsafe {
+            let size = libc::fcntl(self.as_raw_fd(), libc::F_GETPIPE_SZ);
+            if size == -1 {
+                return_errno!("fcntl");
+            }
+            Ok(size as _)
+        }
+    }
+
+    /// Resizes the pipe buffer and returns the new capacity, which the kernel
+    /// rounds up to a power-of-two number of pages. `size` may not exceed
+    /// [`max_capacity`].
+    pub fn set_capacity(&mut self, size: usize) -> Result<usize> {
+        if size > max_capacity()? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "pipe capacity above pipe-max-size",
+            )
+            .into());
+        }
+        unsafe {
+            let size = libc::fcntl(self.as_raw_fd(), libc::F_SETPIPE_SZ, size as libc::c_int);
+            if size == -1 {
+                return_errno!("fcntl");
+            }
+            Ok(size as _)
+        }
+    }
+
+    /// Maps the pages behind `bufs` into the pipe instead of copying them.
+    ///
+    /// The pages are referenced, not copied, until the reader consumes them:
+    /// modifying the buffers before that changes what the reader sees. With
+    /// `SpliceFlags::GIFT` the pages are handed over to the kernel for good.
+    pub fn vmsplice(&mut self, bufs: &[IoSlice<'_>], flags: SpliceFlags) -> Result<usize> {
+        unsafe {
+            let n = libc::vmsplice(
+                self.as_raw_fd(),
+                bufs.as_ptr() as *const libc::iovec,
+                bufs.len(),
+                flags.bits(),
+            );
+            if n == -1 {
+                return_errno!("vmsplice");
+            }
+            Ok(n as _)
+        }
+    }
+}
+
+fn splice(
+    fd_in: unix_io::RawFd,
+    fd_out: unix_io::RawFd,
+    len: usize,
+    flags: SpliceFlags,
+) -> Result<usize> {
+    unsafe {
+        let n = libc::splice(
+            fd_in,
+            ptr::null_mut(),
+            fd_out,
+            ptr::null_mut(),
+            len,
+            flags.bits(),
+        );
+        if n == -1 {
+            return_errno!("splice");
+        }
+        Ok(n as _)
+    }
+}
+
+impl AsFd for PipeReader {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl AsRawFd for PipeReader {
+    fn as_raw_fd(&self) -> unix_io::RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for PipeReader {
+    fn into_raw_fd(self) -> unix_io::RawFd {
+        self.0.into_raw_fd()
+    }
+}
+
+impl From<PipeReader> for OwnedFd {
+    fn from(end: PipeReader) -> OwnedFd {
+        end.0
+    }
+}
+
+impl From<OwnedFd> for PipeReader {
+    fn from(fd: OwnedFd) -> PipeReader {
+        PipeReader(fd)
+    }
+}
+
+impl AsFd for PipeWriter {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl AsRawFd for PipeWriter {
+    fn as_raw_fd(&self) -> unix_io::RawFd {
+        self.0.as_raw_fd()
     }
 }
 
+impl IntoRawFd for PipeWriter {
+    fn into_raw_fd(self) -> unix_io::RawFd {
+        self.0.into_raw_fd()
+    }
+}
+
+impl From<PipeWriter> for OwnedFd {
+    fn from(end: PipeWriter) -> OwnedFd {
+        end.0
+    }
+}
+
+impl From<OwnedFd> for PipeWriter {
+    fn from(fd: OwnedFd) -> PipeWriter {
+        PipeWriter(fd)
+    }
+}
+
+/// The largest capacity an unprivileged process may give a pipe.
+pub fn max_capacity() -> Result<usize> {
+    Ok(fs::read_to_string(PIPE_MAX_SIZE)?.trim().parse()?)
+}
+
 pub fn pipe() -> Result<(PipeReader, PipeWriter)> {
     unsafe {
         let mut fds: [libc::c_int; 2] = [0, 0];
@@ -29,64 +246,540 @@ pub fn pipe() -> Result<(PipeReader, PipeWriter)> {
         if ret == -1 {
             return_errno!("pipe");
         }
-        Ok((PipeReader(RawFd(fds[0])), PipeWriter(RawFd(fds[1]))))
+        Ok((
+            PipeReader(OwnedFd::from_raw_fd(fds[0])),
+            PipeWriter(OwnedFd::from_raw_fd(fds[1])),
+        ))
     }
 }
 
-pub fn pipe2(flags: isize) -> Result<(PipeReader, PipeWriter)> {
+pub fn pipe2(flags: PipeFlags) -> Result<(PipeReader, PipeWriter)> {
     unsafe {
         let mut fds: [libc::c_int; 2] = [0, 0];
-        let ret = libc::pipe2(fds.as_mut_ptr(), flags as _);
+        let ret = libc::pipe2(fds.as_mut_ptr(), flags.bits());
         if ret == -1 {
             return_errno!("pipe2");
         }
-        Ok((PipeReader(RawFd(fds[0])), PipeWriter(RawFd(fds[1]))))
+        Ok((
+            PipeReader(OwnedFd::from_raw_fd(fds[0])),
+            PipeWriter(OwnedFd::from_raw_fd(fds[1])),
+        ))
     }
 }
 
-pub struct Fifo {
-    raw: RawFd,
+/// The read end of a packet-mode pipe: every `recv` returns one packet.
+#[derive(Debug)]
+pub struct PacketReader(OwnedFd);
+/// The write end of a packet-mode pipe: every `send` is one packet.
+#[derive(Debug)]
+pub struct PacketWriter(OwnedFd);
+
+impl PacketReader {
+    pub fn try_clone(&self) -> Result<PacketReader> {
+        Ok(PacketReader(fd::try_clone(self.as_fd())?))
+    }
+
+    pub fn close(self) -> Result<()> {
+        fd::close(self.0)
+    }
+
+    /// Reads the next packet into `buf` and returns its length, or 0 once
+    /// every writer is gone. Bytes of a packet that don't fit in `buf` are
+    /// discarded, so `buf` should hold at least [`PIPE_BUF`] bytes.
+    pub fn recv(&mut self, buf: &mut [u8]) -> Result<usize> {
+        Ok(fd::read(self.as_fd(), buf)?)
+    }
+}
+
+impl PacketWriter {
+    pub fn try_clone(&self) -> Result<PacketWriter> {
+        Ok(PacketWriter(fd::try_clone(self.as_fd())?))
+    }
+
+    pub fn close(self) -> Result<()> {
+        fd::close(self.0)
+    }
+
+    /// Writes `packet` atomically as a single packet. Packets larger than
+    /// [`PIPE_BUF`] are rejected, since the kernel would split them.
+    pub fn send(&mut self, packet: &[u8]) -> Result<()> {
+        if packet.len() > PIPE_BUF {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidInput, "packet larger than PIPE_BUF").into(),
+            );
+        }
+        let n = fd::write(self.as_fd(), packet)?;
+        debug_assert_eq!(n, packet.len());
+        Ok(())
+    }
+}
+
+impl AsFd for PacketReader {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl AsRawFd for PacketReader {
+    fn as_raw_fd(&self) -> unix_io::RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for PacketReader {
+    fn into_raw_fd(self) -> unix_io::RawFd {
+        self.0.into_raw_fd()
+    }
+}
+
+impl From<PacketReader> for OwnedFd {
+    fn from(end: PacketReader) -> OwnedFd {
+        end.0
+    }
+}
+
+impl From<OwnedFd> for PacketReader {
+    fn from(fd: OwnedFd) -> PacketReader {
+        PacketReader(fd)
+    }
+}
+
+impl AsFd for PacketWriter {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl AsRawFd for PacketWriter {
+    fn as_raw_fd(&self) -> unix_io::RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for PacketWriter {
+    fn into_raw_fd(self) -> unix_io::RawFd {
+        self.0.into_raw_fd()
+    }
+}
+
+impl From<PacketWriter> for OwnedFd {
+    fn from(end: PacketWriter) -> OwnedFd {
+        end.0
+    }
+}
+
+impl From<OwnedFd> for PacketWriter {
+    fn from(fd: OwnedFd) -> PacketWriter {
+        PacketWriter(fd)
+    }
+}
+
+/// Creates a pipe in packet mode (`pipe2` with `O_DIRECT`). `flags` may add
+/// `CLOEXEC` and `NONBLOCK`.
+pub fn packet_pipe(flags: PipeFlags) -> Result<(PacketReader, PacketWriter)> {
+    unsafe {
+        let mut fds: [libc::c_int; 2] = [0, 0];
+        let ret = libc::pipe2(fds.as_mut_ptr(), (flags | PipeFlags::DIRECT).bits());
+        if ret == -1 {
+            return_errno!("pipe2");
+        }
+        Ok((
+            PacketReader(OwnedFd::from_raw_fd(fds[0])),
+            PacketWriter(OwnedFd::from_raw_fd(fds[1])),
+        ))
+    }
+}
+
+/// Which way a FIFO is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// Linux only: never blocks on open and keeps the FIFO alive even with
+    /// no other reader or writer.
+    ReadWrite,
+}
+
+impl Access {
+    fn flags(&self) -> OpenFlags {
+        match self {
+            Access::Read => OpenFlags::RDONLY,
+            Access::Write => OpenFlags::WRONLY,
+            Access::ReadWrite => OpenFlags::RDWR,
+        }
+    }
+}
+
+/// The path of a FIFO, removed when the last handle of its owner goes away.
+#[derive(Debug)]
+struct FifoPath {
     path: String,
+    owner: AtomicBool,
 }
 
-impl Fifo {
-    pub fn new(path: &str, flags: isize, mode: isize) -> Result<Fifo> {
-        if fs::metadata(path).is_ok() {
-            return Err(io::Error::from(io::ErrorKind::AlreadyExists).into());
+impl Drop for FifoPath {
+    fn drop(&mut self) {
+        if *self.owner.get_mut() {
+            match fs::remove_file(&self.path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    error!("remove {}: {}", self.path, err)
+                }
+                _ => {}
+            }
         }
+    }
+}
+
+/// A named pipe.
+///
+/// [`create`](Self::create) makes the FIFO and owns its path, unlinking it
+/// on drop; [`open`](Self::open) joins an existing one. Blocking opens wait
+/// for the other side, as `open(2)` does on a FIFO.
+///
+/// Turning a FIFO handle or half into a raw or owned descriptor leaves the
+/// path in place, since the descriptor outlives the handle; remove it with
+/// [`unlink`](Self::unlink) first if it should go.
+pub struct Fifo {
+    raw: OwnedFd,
+    access: Access,
+    path: Arc<FifoPath>,
+}
+
+impl Fifo {
+    /// Creates the FIFO with permissions `perm` and opens it. Fails if `path`
+    /// already exists.
+    pub fn create(path: &str, access: Access, flags: OpenFlags, perm: Mode) -> Result<Fifo> {
         unsafe {
             let c_path = CString::new(path)?;
-            let ret = libc::mkfifo(c_path.as_ptr(), mode as _);
-            if ret == -1 {
-                return_errno!("mkfifo");
+            if libc::mkfifo(c_path.as_ptr(), perm.bits()) == -1 {
+                return_errno!("mkfifo", path);
             }
-            let fd = libc::open(c_path.as_ptr(), flags as _);
+        }
+        let raw = match open_fifo(path, access.flags() | flags) {
+            Ok(raw) => raw,
+            Err(err) => {
+                let _ = fs::remove_file(path);
+                return Err(err);
+            }
+        };
+        Ok(Fifo {
+            raw,
+            access,
+            path: FifoPath::new(path, true),
+        })
+    }
+
+    /// Opens an existing FIFO. With `OpenFlags::NONBLOCK`, opening for writing while
+    /// no process has it open for reading fails with [`Error::FifoNoReader`].
+    pub fn open(path: &str, access: Access, flags: OpenFlags) -> Result<Fifo> {
+        Ok(Fifo {
+            raw: open_fifo(path, access.flags() | flags)?,
+            access,
+            path: FifoPath::new(path, false),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path.path
+    }
+
+    /// Duplicates the handle; the path is shared with the duplicate and
+    /// unlinked, if owned, once every handle is dropped.
+    pub fn try_clone(&self) -> Result<Fifo> {
+        Ok(Fifo {
+            raw: fd::try_clone(self.raw.as_fd())?,
+            access: self.access,
+            path: self.path.clone(),
+        })
+    }
+
+    /// Closes the descriptor; the path goes away, if owned, once every
+    /// handle sharing it is closed or dropped.
+    pub fn close(self) -> Result<()> {
+        fd::close(self.raw)
+    }
+
+    pub fn access(&self) -> Access {
+        self.access
+    }
+
+    pub fn owner(&self) -> bool {
+        self.path.owner.load(Ordering::Relaxed)
+    }
+
+    /// Sets whether dropping this handle (or both its halves) unlinks the path.
+    pub fn set_owner(&mut self, owner: bool) {
+        self.path.owner.store(owner, Ordering::Relaxed);
+    }
+
+    /// Removes the path now, owned or not. Open handles keep working.
+    pub fn unlink(&self) -> Result<()> {
+        self.path.unlink()
+    }
+
+    /// Splits an [`Access::ReadWrite`] FIFO into halves sharing the open file.
+    /// The path is unlinked, if owned, once both halves are dropped.
+    pub fn split(self) -> Result<(FifoReader, FifoWriter)> {
+        if self.access != Access::ReadWrite {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidInput, "FIFO not opened read-write").into(),
+            );
+        }
+        unsafe {
+            let cloexec = libc::fcntl(self.raw.as_raw_fd(), libc::F_GETFD) & libc::FD_CLOEXEC != 0;
+            let cmd = if cloexec {
+                libc::F_DUPFD_CLOEXEC
+            } else {
+                libc::F_DUPFD
+            };
+            let fd = libc::fcntl(self.raw.as_raw_fd(), cmd, 0);
             if fd == -1 {
-                return_errno!("open");
+                return_errno!("fcntl");
             }
-            Ok(Fifo { raw: RawFd(fd), path: path.to_string() })
+            Ok((
+                FifoReader {
+                    raw: self.raw,
+                    path: self.path.clone(),
+                },
+                FifoWriter {
+                    raw: OwnedFd::from_raw_fd(fd),
+                    path: self.path,
+                },
+            ))
         }
     }
 }
 
+impl FifoPath {
+    fn new(path: &str, owner: bool) -> Arc<FifoPath> {
+        Arc::new(FifoPath {
+            path: path.to_string(),
+            owner: AtomicBool::new(owner),
+        })
+    }
+
+    fn unlink(&self) -> Result<()> {
+        self.owner.store(false, Ordering::Relaxed);
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+
+    fn disown(&self) {
+        self.owner.store(false, Ordering::Relaxed);
+    }
+}
+
+fn open_fifo(path: &str, flags: OpenFlags) -> Result<OwnedFd> {
+    unsafe {
+        let c_path = CString::new(path)?;
+        let fd = libc::open(c_path.as_ptr(), flags.bits());
+        if fd == -1 {
+            if libc_errno() == libc::ENXIO {
+                return Err(Error::FifoNoReader(path.to_string()));
+            }
+            return_errno!("open", path);
+        }
+        Ok(OwnedFd::from_raw_fd(fd))
+    }
+}
+
 impl io::Read for Fifo {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.raw.read(buf)
+        fd::read(self.raw.as_fd(), buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        fd::read_vectored(self.raw.as_fd(), bufs)
     }
 }
 
 impl io::Write for Fifo {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.raw.write(buf)
+        fd::write(self.raw.as_fd(), buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        fd::write_vectored(self.raw.as_fd(), bufs)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.raw.flush()
+        Ok(())
     }
 }
 
-impl Drop for Fifo {
-    fn drop(&mut self) {
-        let _ = fs::remove_file(&self.path);
+impl AsRawFd for Fifo {
+    fn as_raw_fd(&self) -> unix_io::RawFd {
+        self.raw.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for Fifo {
+    /// Keeps the path; see [`Fifo`].
+    fn into_raw_fd(self) -> unix_io::RawFd {
+        OwnedFd::from(self).into_raw_fd()
+    }
+}
+
+/// The read half of a FIFO.
+pub struct FifoReader {
+    raw: OwnedFd,
+    path: Arc<FifoPath>,
+}
+
+/// The write half of a FIFO.
+pub struct FifoWriter {
+    raw: OwnedFd,
+    path: Arc<FifoPath>,
+}
+
+impl FifoReader {
+    pub fn open(path: &str, flags: OpenFlags) -> Result<FifoReader> {
+        Ok(FifoReader {
+            raw: open_fifo(path, OpenFlags::RDONLY | flags)?,
+            path: FifoPath::new(path, false),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path.path
+    }
+
+    pub fn try_clone(&self) -> Result<FifoReader> {
+        Ok(FifoReader {
+            raw: fd::try_clone(self.raw.as_fd())?,
+            path: self.path.clone(),
+        })
+    }
+
+    pub fn close(self) -> Result<()> {
+        fd::close(self.raw)
+    }
+
+    /// See [`Fifo::unlink`].
+    pub fn unlink(&self) -> Result<()> {
+        self.path.unlink()
+    }
+}
+
+impl FifoWriter {
+    /// With `OpenFlags::NONBLOCK`, fails with [`Error::FifoNoReader`] while no process
+    /// has the FIFO open for reading.
+    pub fn open(path: &str, flags: OpenFlags) -> Result<FifoWriter> {
+        Ok(FifoWriter {
+            raw: open_fifo(path, OpenFlags::WRONLY | flags)?,
+            path: FifoPath::new(path, false),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path.path
+    }
+
+    pub fn try_clone(&self) -> Result<FifoWriter> {
+        Ok(FifoWriter {
+            raw: fd::try_clone(self.raw.as_fd())?,
+            path: self.path.clone(),
+        })
+    }
+
+    pub fn close(self) -> Result<()> {
+        fd::close(self.raw)
+    }
+
+    /// See [`Fifo::unlink`].
+    pub fn unlink(&self) -> Result<()> {
+        self.path.unlink()
+    }
+}
+
+impl io::Read for FifoReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        fd::read(self.raw.as_fd(), buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        fd::read_vectored(self.raw.as_fd(), bufs)
+    }
+}
+
+impl io::Write for FifoWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        fd::write(self.raw.as_fd(), buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        fd::write_vectored(self.raw.as_fd(), bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsRawFd for FifoReader {
+    fn as_raw_fd(&self) -> unix_io::RawFd {
+        self.raw.as_raw_fd()
+    }
+}
+
+impl AsRawFd for FifoWriter {
+    fn as_raw_fd(&self) -> unix_io::RawFd {
+        self.raw.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for FifoReader {
+    /// Keeps the path; see [`Fifo`].
+    fn into_raw_fd(self) -> unix_io::RawFd {
+        OwnedFd::from(self).into_raw_fd()
+    }
+}
+
+impl IntoRawFd for FifoWriter {
+    /// Keeps the path; see [`Fifo`].
+    fn into_raw_fd(self) -> unix_io::RawFd {
+        OwnedFd::from(self).into_raw_fd()
+    }
+}
+
+impl AsFd for Fifo {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.raw.as_fd()
+    }
+}
+
+impl From<Fifo> for OwnedFd {
+    /// Keeps the path; see [`Fifo`].
+    fn from(fifo: Fifo) -> OwnedFd {
+        fifo.path.disown();
+        fifo.raw
+    }
+}
+
+impl AsFd for FifoReader {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.raw.as_fd()
+    }
+}
+
+impl From<FifoReader> for OwnedFd {
+    /// Keeps the path; see [`Fifo`].
+    fn from(fifo: FifoReader) -> OwnedFd {
+        fifo.path.disown();
+        fifo.raw
+    }
+}
+
+impl AsFd for FifoWriter {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.raw.as_fd()
+    }
+}
+
+impl From<FifoWriter> for OwnedFd {
+    /// Keeps the path; see [`Fifo`].
+    fn from(fifo: FifoWriter) -> OwnedFd {
+        fifo.path.disown();
+        fifo.raw
     }
 }
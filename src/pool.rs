@@ -0,0 +1,207 @@
+use crate::flags::{Mode, OpenFlags};
+use crate::sem::{Semaphore, SemaphoreLike, SemaphorePermit};
+use crate::shm::{Shm, ShmSafe};
+use crate::Result;
+use log::error;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use std::{io, mem, slice};
+
+const FREE: u32 = 0;
+const TAKEN: u32 = 1;
+
+#[derive(Debug)]
+#[repr(C)]
+struct Header {
+    slots: u32,
+    slot_size: u32,
+}
+
+unsafe impl ShmSafe for Header {}
+
+/// A fixed set of equally sized slots in shared memory, handed out to worker
+/// processes one at a time.
+///
+/// A named semaphore counts the free slots and a table of flags next to the
+/// slots records which ones are taken. Both live under `name`, so every
+/// process opening the pool with the same parameters sees the same slots.
+/// A process that dies while holding a slot leaks it.
+#[derive(Debug)]
+pub struct ResourcePool {
+    sem: Semaphore,
+    shm: Shm,
+    slots: usize,
+    slot_size: usize,
+    owner: bool,
+}
+
+impl ResourcePool {
+    pub fn create(name: &str, slots: usize, slot_size: usize) -> Result<ResourcePool> {
+        let header = Header {
+            slots: u32::try_from(slots).map_err(|_| too_large())?,
+            slot_size: u32::try_from(slot_size).map_err(|_| too_large())?,
+        };
+        let shm = Shm::open(name, Self::total_size(slots, slot_size)?, true)?;
+        unsafe {
+            shm.place(0, header)?;
+        }
+        // 信号量最后创建，open 成功即说明共享内存已初始化
+        let sem = Semaphore::open(
+            name,
+            OpenFlags::CREAT | OpenFlags::EXCL | OpenFlags::RDWR,
+            Mode::from_bits_truncate(0o666),
+            slots,
+        )?;
+        Ok(ResourcePool {
+            sem,
+            shm,
+            slots,
+            slot_size,
+            owner: true,
+        })
+    }
+
+    pub fn open(name: &str, slots: usize, slot_size: usize) -> Result<ResourcePool> {
+        let sem = Semaphore::open(name, OpenFlags::RDWR, Mode::empty(), 0)?;
+        let shm = Shm::open(name, Self::total_size(slots, slot_size)?, false)?;
+        let header = unsafe { shm.attach::<Header>(0)? };
+        if header.slots as usize != slots || header.slot_size as usize != slot_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "pool layout mismatch").into());
+        }
+        Ok(ResourcePool {
+            sem,
+            shm,
+            slots,
+            slot_size,
+            owner: false,
+        })
+    }
+
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    pub fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+
+    pub fn available(&self) -> Result<usize> {
+        self.sem.value()
+    }
+
+    pub fn checkout(&self) -> Result<PoolSlot<'_>> {
+        let permit = self.sem.acquire()?;
+        Ok(self.take(permit))
+    }
+
+    pub fn try_checkout(&self) -> Result<Option<PoolSlot<'_>>> {
+        Ok(self.sem.try_acquire()?.map(|permit| self.take(permit)))
+    }
+
+    pub fn checkout_timeout(&self, timeout: Duration) -> Result<Option<PoolSlot<'_>>> {
+        Ok(self
+            .sem
+            .acquire_timeout(timeout)?
+            .map(|permit| self.take(permit)))
+    }
+
+    /// Closes the pool and, if this process created it, unlinks its
+    /// semaphore, reporting the errors that dropping would only log.
+    pub fn close(mut self) -> Result<()> {
+        self.release()
+    }
+
+    fn release(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        if self.owner {
+            self.owner = false;
+            result = Semaphore::unlink(self.sem.name());
+        }
+        result.and(self.sem.release()).and(self.shm.release())
+    }
+
+    fn take<'a>(&'a self, permit: SemaphorePermit<'a, Semaphore>) -> PoolSlot<'a> {
+        // 持有 permit 即保证至少有一个空闲 slot
+        loop {
+            for index in 0..self.slots {
+                if self
+                    .flag(index)
+                    .compare_exchange(FREE, TAKEN, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return PoolSlot {
+                        pool: self,
+                        index,
+                        _permit: permit,
+                    };
+                }
+            }
+        }
+    }
+
+    fn flag(&self, index: usize) -> &AtomicU32 {
+        let offset = mem::size_of::<Header>() + index * mem::size_of::<AtomicU32>();
+        unsafe { &*(self.shm.as_ptr().add(offset) as *const AtomicU32) }
+    }
+
+    fn slot_ptr(&self, index: usize) -> *mut u8 {
+        let offset = Self::data_offset(self.slots) + index * self.slot_size;
+        unsafe { self.shm.as_ptr().add(offset) as *mut u8 }
+    }
+
+    fn data_offset(slots: usize) -> usize {
+        let table = mem::size_of::<Header>() + slots * mem::size_of::<AtomicU32>();
+        (table + 63) & !63
+    }
+
+    fn total_size(slots: usize, slot_size: usize) -> Result<usize> {
+        // 先确认标志表不会溢出，data_offset 之后才能放心用
+        slots
+            .checked_mul(mem::size_of::<AtomicU32>())
+            .and_then(|table| table.checked_add(mem::size_of::<Header>() + 63))
+            .and_then(|_| slots.checked_mul(slot_size))
+            .and_then(|data| data.checked_add(Self::data_offset(slots)))
+            .ok_or_else(too_large)
+    }
+}
+
+fn too_large() -> crate::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "pool too large").into()
+}
+
+impl Drop for ResourcePool {
+    fn drop(&mut self) {
+        if let Err(err) = self.release() {
+            error!("{}", err);
+        }
+    }
+}
+
+/// A slot checked out of a [`ResourcePool`], returned to it on drop.
+#[derive(Debug)]
+pub struct PoolSlot<'a> {
+    pool: &'a ResourcePool,
+    index: usize,
+    _permit: SemaphorePermit<'a, Semaphore>,
+}
+
+impl PoolSlot<'_> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.pool.slot_ptr(self.index), self.pool.slot_size) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.pool.slot_ptr(self.index), self.pool.slot_size) }
+    }
+}
+
+impl Drop for PoolSlot<'_> {
+    fn drop(&mut self) {
+        // 先释放 slot，permit 随后 post
+        self.pool.flag(self.index).store(FREE, Ordering::Release);
+    }
+}
@@ -0,0 +1,719 @@
+use crate::errors::libc_errno;
+use crate::flags::{PipeFlags, WaitOptions};
+use crate::marshal::Marshal;
+use crate::{pipe, Error, Result};
+use std::any::Any;
+use std::ffi::CString;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::time::{Duration, Instant};
+use std::{cmp, fmt, io, mem, panic, ptr, thread};
+
+cfg_if::cfg_if! {
+    if #[cfg(all(
+        target_os = "android",
+        any(
+            target_arch = "arm",
+            target_arch = "aarch64",
+            target_arch = "x86",
+            target_arch = "x86_64",
+            target_arch = "riscv64"
+        )
+    ))] {
+        // libc 尚未为 Android 导出，这些编号在以上架构上相同
+        const SYS_CLOSE_RANGE: libc::c_long = 436;
+        const CLOSE_RANGE_CLOEXEC: libc::c_uint = 1 << 2;
+        const SYS_PIDFD_SEND_SIGNAL: libc::c_long = 424;
+        const SYS_PIDFD_OPEN: libc::c_long = 434;
+    } else {
+        use libc::{
+            SYS_close_range as SYS_CLOSE_RANGE, SYS_pidfd_open as SYS_PIDFD_OPEN,
+            SYS_pidfd_send_signal as SYS_PIDFD_SEND_SIGNAL, CLOSE_RANGE_CLOEXEC,
+        };
+    }
+}
+
+/// What [`fork`](crate::fork) returns on each side of the fork.
+#[derive(Debug)]
+pub enum Fork {
+    Parent(Child),
+    Child,
+}
+
+/// How a child changed state, decoded from a `waitpid` status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Exited normally with this code.
+    Exited(i32),
+    /// Killed by a signal.
+    Signaled { signal: i32, core_dumped: bool },
+    /// Stopped by a signal; only reported with `WaitOptions::UNTRACED`.
+    Stopped(i32),
+    /// Resumed by `SIGCONT`; only reported with `WaitOptions::CONTINUED`.
+    Continued,
+}
+
+impl ExitStatus {
+    pub fn from_raw(status: libc::c_int) -> ExitStatus {
+        if libc::WIFEXITED(status) {
+            ExitStatus::Exited(libc::WEXITSTATUS(status))
+        } else if libc::WIFSIGNALED(status) {
+            ExitStatus::Signaled {
+                signal: libc::WTERMSIG(status),
+                core_dumped: libc::WCOREDUMP(status),
+            }
+        } else if libc::WIFSTOPPED(status) {
+            ExitStatus::Stopped(libc::WSTOPSIG(status))
+        } else {
+            ExitStatus::Continued
+        }
+    }
+
+    /// Whether the child exited with code 0.
+    pub fn success(&self) -> bool {
+        *self == ExitStatus::Exited(0)
+    }
+
+    /// The exit code, if the child exited normally.
+    pub fn code(&self) -> Option<i32> {
+        match *self {
+            ExitStatus::Exited(code) => Some(code),
+            _ => None,
+        }
+    }
+
+    fn from_siginfo(info: &libc::siginfo_t) -> ExitStatus {
+        let status = unsafe { info.si_status() };
+        match info.si_code {
+            libc::CLD_EXITED => ExitStatus::Exited(status),
+            libc::CLD_KILLED => ExitStatus::Signaled {
+                signal: status,
+                core_dumped: false,
+            },
+            libc::CLD_DUMPED => ExitStatus::Signaled {
+                signal: status,
+                core_dumped: true,
+            },
+            libc::CLD_CONTINUED => ExitStatus::Continued,
+            _ => ExitStatus::Stopped(status),
+        }
+    }
+
+    /// Whether the child is gone, as opposed to stopped or continued.
+    pub fn is_terminated(&self) -> bool {
+        matches!(self, ExitStatus::Exited(_) | ExitStatus::Signaled { .. })
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ExitStatus::Exited(code) => write!(f, "exit code: {}", code),
+            ExitStatus::Signaled {
+                signal,
+                core_dumped,
+            } => {
+                write!(f, "signal: {}", signal)?;
+                if core_dumped {
+                    write!(f, " (core dumped)")?;
+                }
+                Ok(())
+            }
+            ExitStatus::Stopped(signal) => write!(f, "stopped by signal: {}", signal),
+            ExitStatus::Continued => write!(f, "continued"),
+        }
+    }
+}
+
+/// A descriptor that refers to one process rather than to a pid, so it can't
+/// be confused with a later process that reuses the pid (Linux 5.3+).
+///
+/// It becomes readable once the process exits, so it can be polled next to
+/// other descriptors.
+#[derive(Debug)]
+pub struct PidFd {
+    fd: OwnedFd,
+    pid: i32,
+}
+
+impl PidFd {
+    /// Opens a pidfd for `pid`. This is race-free for a child that hasn't
+    /// been reaped yet, since its pid can't be reused until then.
+    pub fn open(pid: i32) -> Result<PidFd> {
+        unsafe {
+            let fd = libc::syscall(SYS_PIDFD_OPEN, pid, 0);
+            if fd == -1 {
+                return_errno!("pidfd_open");
+            }
+            Ok(PidFd {
+                fd: OwnedFd::from_raw_fd(fd as _),
+                pid,
+            })
+        }
+    }
+
+    pub fn send_signal(&self, signal: i32) -> Result<()> {
+        unsafe {
+            let ret = libc::syscall(
+                SYS_PIDFD_SEND_SIGNAL,
+                self.fd.as_raw_fd(),
+                signal,
+                ptr::null::<libc::siginfo_t>(),
+                0,
+            );
+            if ret == -1 {
+                return_errno!("pidfd_send_signal");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// `waitid(P_PIDFD)` on a child, taking the same `options` as
+    /// [`Child::wait_with`]; terminated children are always reported.
+    /// Falls back to `waitid(P_PID)` before Linux 5.4, which is still safe
+    /// for a child that hasn't been reaped.
+    pub fn wait_with(&self, options: WaitOptions) -> Result<Option<ExitStatus>> {
+        unsafe {
+            let mut info: libc::siginfo_t = mem::zeroed();
+            let options = libc::WEXITED | options.bits();
+            let mut ret = libc::waitid(libc::P_PIDFD, self.fd.as_raw_fd() as _, &mut info, options);
+            if ret == -1 && libc_errno() == libc::EINVAL {
+                ret = libc::waitid(libc::P_PID, self.pid as _, &mut info, options);
+            }
+            if ret == -1 {
+                return_errno!("waitid");
+            }
+            // WNOHANG 且无状态变化时 si_pid 保持为 0
+            if info.si_pid() == 0 {
+                return Ok(None);
+            }
+            Ok(Some(ExitStatus::from_siginfo(&info)))
+        }
+    }
+
+    /// Waits at most `timeout` for the process to exit, without reaping it.
+    pub fn poll(&self, timeout: Duration) -> Result<bool> {
+        let mut pfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = cmp::min(timeout.as_millis(), libc::c_int::MAX as u128) as libc::c_int;
+        unsafe {
+            let n = libc::poll(&mut pfd, 1, timeout);
+            if n == -1 {
+                return_errno!("poll");
+            }
+            Ok(n == 1)
+        }
+    }
+}
+
+impl AsFd for PidFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for PidFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl From<PidFd> for OwnedFd {
+    fn from(pidfd: PidFd) -> OwnedFd {
+        pidfd.fd
+    }
+}
+
+/// A forked child process of this process.
+///
+/// Where the kernel supports it, `Child` holds a [`PidFd`] and signals and
+/// waits through it. Without one, the pid is only valid until the child is
+/// reaped. `Child` remembers the status once a wait has reaped it, so later
+/// waits return it again and [`kill`](Self::kill) can't hit an unrelated
+/// process that reused the pid. Dropping a `Child` doesn't wait for it.
+#[derive(Debug)]
+pub struct Child {
+    pid: i32,
+    pidfd: Option<PidFd>,
+    status: Option<ExitStatus>,
+}
+
+impl Child {
+    /// Wraps the pid of a child of this process, e.g. one forked elsewhere,
+    /// and opens a pidfd for it if the kernel supports them.
+    pub fn from_pid(pid: i32) -> Child {
+        Child {
+            pid,
+            pidfd: PidFd::open(pid).ok(),
+            status: None,
+        }
+    }
+
+    pub fn id(&self) -> i32 {
+        self.pid
+    }
+
+    /// The child's pidfd, for polling it in an event loop. `None` on kernels
+    /// without pidfd support.
+    pub fn pidfd(&self) -> Option<&PidFd> {
+        self.pidfd.as_ref()
+    }
+
+    /// Blocks until the child terminates and reaps it.
+    pub fn wait(&mut self) -> Result<ExitStatus> {
+        loop {
+            if let Some(status) = self.wait_with(WaitOptions::empty())? {
+                if status.is_terminated() {
+                    return Ok(status);
+                }
+            }
+        }
+    }
+
+    /// Reaps the child if it has terminated, without blocking.
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>> {
+        Ok(self
+            .wait_with(WaitOptions::NOHANG)?
+            .filter(ExitStatus::is_terminated))
+    }
+
+    /// Waits at most `timeout` for the child to terminate. Returns `None` if
+    /// it is still running afterwards.
+    pub fn wait_timeout(&mut self, timeout: Duration) -> Result<Option<ExitStatus>> {
+        let deadline = Instant::now() + timeout;
+        let mut delay = Duration::from_millis(1);
+        loop {
+            if let Some(status) = self.try_wait()? {
+                return Ok(Some(status));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            match &self.pidfd {
+                Some(pidfd) => match pidfd.poll(deadline - now) {
+                    Err(err) if err.as_errno() != Some(libc::EINTR) => return Err(err),
+                    _ => {}
+                },
+                None => {
+                    thread::sleep(cmp::min(delay, deadline - now));
+                    delay = cmp::min(delay * 2, Duration::from_millis(50));
+                }
+            }
+        }
+    }
+
+    /// Waits for a state change with `options`, through the pidfd if there
+    /// is one. Returns `None` when `NOHANG` is set and the child hasn't changed
+    /// state. Stopped and continued states are reported but don't reap.
+    pub fn wait_with(&mut self, options: WaitOptions) -> Result<Option<ExitStatus>> {
+        if let Some(status) = self.status {
+            return Ok(Some(status));
+        }
+        let status = loop {
+            let status = match &self.pidfd {
+                Some(pidfd) => pidfd.wait_with(options),
+                None => crate::waitpid(self.pid, options).map(|s| s.map(|(_, status)| status)),
+            };
+            match status {
+                Ok(status) => break status,
+                Err(err) if err.as_errno() == Some(libc::EINTR) => continue,
+                Err(err) => return Err(err),
+            }
+        };
+        if let Some(status) = status.filter(ExitStatus::is_terminated) {
+            self.status = Some(status);
+        }
+        Ok(status)
+    }
+
+    /// Sends `signal` to the child. Fails once the child has been reaped.
+    pub fn kill(&self, signal: i32) -> Result<()> {
+        if self.status.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "child already reaped").into());
+        }
+        if let Some(pidfd) = &self.pidfd {
+            return pidfd.send_signal(signal);
+        }
+        unsafe {
+            if libc::kill(self.pid, signal) == -1 {
+                return_errno!("kill");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Forks and runs `f` in the child, like `thread::spawn` for processes.
+///
+/// The child sends the closure's return value, or its panic message, back
+/// through a pipe and exits without running destructors or `atexit`
+/// handlers. Values captured by `f` are dropped unused in the parent, which
+/// closes the parent's copy of any descriptor moved into the closure. As
+/// with any `fork`, only the calling thread exists in the child.
+pub fn run_in_child<T, F>(f: F) -> Result<JoinHandle<T>>
+where
+    T: Marshal,
+    F: FnOnce() -> T,
+{
+    let (reader, mut writer) = pipe::pipe2(PipeFlags::CLOEXEC)?;
+    match crate::fork()? {
+        Fork::Child => {
+            drop(reader);
+            let mut buf = Vec::new();
+            match panic::catch_unwind(panic::AssertUnwindSafe(f)) {
+                Ok(value) => {
+                    buf.push(RESULT_OK);
+                    value.marshal(&mut buf);
+                }
+                Err(payload) => {
+                    buf.push(RESULT_PANIC);
+                    buf.extend_from_slice(panic_message(&*payload).as_bytes());
+                }
+            }
+            let code = match writer.write_all(&buf) {
+                Ok(_) => 0,
+                Err(_) => 1,
+            };
+            unsafe { libc::_exit(code) }
+        }
+        Fork::Parent(child) => Ok(JoinHandle {
+            child,
+            reader,
+            _marker: PhantomData,
+        }),
+    }
+}
+
+/// The message a panic was raised with, as `std` prints it.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+const RESULT_OK: u8 = 0;
+const RESULT_PANIC: u8 = 1;
+
+/// The parent's handle on a closure running under [`run_in_child`].
+#[derive(Debug)]
+pub struct JoinHandle<T> {
+    child: Child,
+    reader: pipe::PipeReader,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Marshal> JoinHandle<T> {
+    pub fn child(&self) -> &Child {
+        &self.child
+    }
+
+    pub fn child_mut(&mut self) -> &mut Child {
+        &mut self.child
+    }
+
+    /// Waits for the child to finish and returns the closure's value. Fails
+    /// with [`Error::ChildPanic`](crate::Error::ChildPanic) if it panicked,
+    /// or [`Error::ChildExit`](crate::Error::ChildExit) if the child died
+    /// before sending anything back.
+    pub fn join(mut self) -> Result<T> {
+        let mut buf = Vec::new();
+        let read = self.reader.read_to_end(&mut buf);
+        let status = self.child.wait()?;
+        read?;
+        let (&tag, mut bytes) = match buf.split_first() {
+            Some(split) => split,
+            None => return Err(Error::ChildExit(status)),
+        };
+        match tag {
+            RESULT_OK => T::unmarshal(&mut bytes),
+            _ => Err(Error::ChildPanic(
+                String::from_utf8_lossy(bytes).into_owned(),
+            )),
+        }
+    }
+}
+
+/// Makes this process a child subreaper (`PR_SET_CHILD_SUBREAPER`): orphaned
+/// descendants are reparented to it instead of to init, so a supervisor can
+/// reap its workers' children, e.g. with `waitpid(-1, WNOHANG)`.
+pub fn set_child_subreaper(enabled: bool) -> Result<()> {
+    unsafe {
+        if libc::prctl(libc::PR_SET_CHILD_SUBREAPER, enabled as libc::c_ulong) == -1 {
+            return_errno!("prctl");
+        }
+    }
+    Ok(())
+}
+
+pub fn is_child_subreaper() -> Result<bool> {
+    let mut enabled: libc::c_int = 0;
+    unsafe {
+        if libc::prctl(libc::PR_GET_CHILD_SUBREAPER, &mut enabled) == -1 {
+            return_errno!("prctl");
+        }
+    }
+    Ok(enabled != 0)
+}
+
+/// Forks a child with pipe ends, FIFOs or other descriptors wired to fixed
+/// descriptor numbers, such as its stdin, stdout and stderr.
+///
+/// The builder owns every descriptor handed to it. After forking, the parent
+/// closes them, and the child moves them to their target numbers with
+/// `O_CLOEXEC` cleared. Every other descriptor from 3 up, such as the ends
+/// of pipes the parent keeps, is closed in the child: right after forking
+/// by [`fork`](Self::fork), unless passed to
+/// [`keep_in_child`](Self::keep_in_child), and on exec by
+/// [`spawn`](Self::spawn).
+#[derive(Debug, Default)]
+pub struct ChildBuilder {
+    fds: Vec<(RawFd, RawFd)>,
+    keep: Vec<RawFd>,
+    death_signal: Option<i32>,
+}
+
+impl ChildBuilder {
+    pub fn new() -> ChildBuilder {
+        ChildBuilder::default()
+    }
+
+    pub fn stdin(self, fd: impl IntoRawFd) -> ChildBuilder {
+        self.fd(libc::STDIN_FILENO, fd)
+    }
+
+    pub fn stdout(self, fd: impl IntoRawFd) -> ChildBuilder {
+        self.fd(libc::STDOUT_FILENO, fd)
+    }
+
+    pub fn stderr(self, fd: impl IntoRawFd) -> ChildBuilder {
+        self.fd(libc::STDERR_FILENO, fd)
+    }
+
+    /// Makes `fd` available in the child as descriptor number `target`.
+    pub fn fd(mut self, target: RawFd, fd: impl IntoRawFd) -> ChildBuilder {
+        let fd = fd.into_raw_fd();
+        if let Some(i) = self.fds.iter().position(|&(t, _)| t == target) {
+            let (_, old) = self.fds.swap_remove(i);
+            unsafe { libc::close(old) };
+        }
+        self.fds.push((target, fd));
+        self
+    }
+
+    /// Leaves `fd` open, at the same number, in a child started with
+    /// [`fork`](Self::fork).
+    pub fn keep_in_child(mut self, fd: &impl AsRawFd) -> ChildBuilder {
+        self.keep.push(fd.as_raw_fd());
+        self
+    }
+
+    /// Has the kernel send `signal` to the child when the parent dies
+    /// (`PR_SET_PDEATHSIG`), so workers don't outlive their supervisor.
+    ///
+    /// A parent that dies before the child has set this up is caught by
+    /// checking `getppid` afterwards; the child then raises `signal` itself.
+    /// The signal fires when the forking *thread* exits, and is cleared in
+    /// the child by exec'ing a set-user-ID or set-group-ID program.
+    pub fn parent_death_signal(mut self, signal: i32) -> ChildBuilder {
+        self.death_signal = Some(signal);
+        self
+    }
+
+    /// Forks, with the descriptors already in place in the child and every
+    /// other descriptor closed. Handles the child inherited for descriptors
+    /// closed this way must not be used there.
+    pub fn fork(mut self) -> Result<Fork> {
+        let mut keep = self.targets();
+        keep.extend_from_slice(&self.keep);
+        keep.sort_unstable();
+        self.stage()?;
+        unsafe {
+            let parent = libc::getpid();
+            let pid = libc::fork();
+            if pid == -1 {
+                return_errno!("fork");
+            }
+            if pid == 0 {
+                if self
+                    .setup_child(parent)
+                    .and_then(|_| close_except(&keep, 0))
+                    .is_err()
+                {
+                    libc::_exit(127);
+                }
+                self.fds.clear();
+                return Ok(Fork::Child);
+            }
+            Ok(Fork::Parent(Child::from_pid(pid)))
+        }
+    }
+
+    /// Forks and execs `program` (looked up in `PATH`) with `args`. Fails in
+    /// the parent if the child couldn't set up its descriptors or exec.
+    pub fn spawn(mut self, program: &str, args: &[&str]) -> Result<Child> {
+        let c_program = CString::new(program)?;
+        let c_args = args
+            .iter()
+            .map(|arg| CString::new(*arg))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let mut argv = vec![c_program.as_ptr()];
+        argv.extend(c_args.iter().map(|arg| arg.as_ptr()));
+        argv.push(ptr::null());
+
+        let mut targets = self.targets();
+        targets.sort_unstable();
+
+        // exec 成功时 O_CLOEXEC 的写端随之关闭，父进程读到 EOF
+        let (mut err_reader, low_writer) = pipe::pipe2(PipeFlags::CLOEXEC)?;
+        let above = self.stage()?;
+        // 写端同样移到目标描述符之上，以免被 dup2 覆盖
+        let err_writer = dup_above(low_writer.as_raw_fd(), above)?;
+        drop(low_writer);
+
+        unsafe {
+            let parent = libc::getpid();
+            let pid = libc::fork();
+            if pid == -1 {
+                return_errno!("fork");
+            }
+            if pid == 0 {
+                let errno = match self
+                    .setup_child(parent)
+                    .and_then(|_| close_except(&targets, CLOSE_RANGE_CLOEXEC))
+                {
+                    Ok(_) => {
+                        libc::execvp(c_program.as_ptr(), argv.as_ptr());
+                        libc_errno()
+                    }
+                    Err(errno) => errno,
+                };
+                let bytes = errno.to_ne_bytes();
+                libc::write(err_writer.as_raw_fd(), bytes.as_ptr() as _, bytes.len());
+                libc::_exit(127);
+            }
+            drop(err_writer);
+
+            let mut bytes = [0u8; 4];
+            loop {
+                // 子进程已经 fork 出来，被信号打断要重读，不能丢下它不管
+                return match err_reader.read(&mut bytes) {
+                    Ok(0) => Ok(Child::from_pid(pid)),
+                    Ok(_) => {
+                        libc::waitpid(pid, ptr::null_mut(), 0);
+                        Err(io::Error::from_raw_os_error(i32::from_ne_bytes(bytes)).into())
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => Err(err.into()),
+                };
+            }
+        }
+    }
+
+    fn targets(&self) -> Vec<RawFd> {
+        self.fds.iter().map(|&(target, _)| target).collect()
+    }
+
+    /// Moves every source descriptor above the highest target, so that no
+    /// `dup2` in the child can clobber a source that is still to be placed.
+    /// Returns the number just above the highest target.
+    fn stage(&mut self) -> Result<RawFd> {
+        let above = self.targets().into_iter().max().unwrap_or(0) + 1;
+        for (_, fd) in self.fds.iter_mut() {
+            let high = dup_above(*fd, above)?.into_raw_fd();
+            unsafe { libc::close(*fd) };
+            *fd = high;
+        }
+        Ok(above)
+    }
+
+    /// Runs in the child right after `fork`: async-signal-safe calls only,
+    /// no allocation.
+    fn setup_child(&self, parent: libc::pid_t) -> std::result::Result<(), libc::c_int> {
+        unsafe {
+            if let Some(signal) = self.death_signal {
+                if libc::prctl(libc::PR_SET_PDEATHSIG, signal as libc::c_ulong) == -1 {
+                    return Err(libc_errno());
+                }
+                // 父进程可能在 prctl 之前就已退出，此时不会再收到信号
+                if libc::getppid() != parent {
+                    libc::raise(signal);
+                }
+            }
+            // dup2 产生的新描述符不带 FD_CLOEXEC
+            for &(target, fd) in &self.fds {
+                if libc::dup2(fd, target) == -1 {
+                    return Err(libc_errno());
+                }
+            }
+            for &(_, fd) in &self.fds {
+                libc::close(fd);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Duplicates `fd` to the lowest free number not below `above`, with
+/// `O_CLOEXEC` set.
+fn dup_above(fd: RawFd, above: RawFd) -> Result<OwnedFd> {
+    unsafe {
+        let high = libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, above);
+        if high == -1 {
+            return_errno!("fcntl");
+        }
+        Ok(OwnedFd::from_raw_fd(high))
+    }
+}
+
+/// Closes every descriptor from 3 up except `keep` (sorted), or with
+/// `CLOSE_RANGE_CLOEXEC` in `flags` marks them close-on-exec instead.
+fn close_except(keep: &[RawFd], flags: libc::c_uint) -> std::result::Result<(), libc::c_int> {
+    unsafe {
+        let mut first: RawFd = 3;
+        for &fd in keep.iter().chain(Some(&RawFd::MAX)) {
+            if fd < first {
+                continue;
+            }
+            if fd > first {
+                let last = (fd - 1) as libc::c_uint;
+                let ret = libc::syscall(SYS_CLOSE_RANGE, first as libc::c_uint, last, flags);
+                if ret == -1 {
+                    if libc_errno() != libc::ENOSYS && libc_errno() != libc::EINVAL {
+                        return Err(libc_errno());
+                    }
+                    let max = libc::sysconf(libc::_SC_OPEN_MAX) as RawFd;
+                    for fd in first..fd.min(max) {
+                        if flags & CLOSE_RANGE_CLOEXEC != 0 {
+                            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                        } else {
+                            libc::close(fd);
+                        }
+                    }
+                }
+            }
+            first = fd.saturating_add(1);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ChildBuilder {
+    fn drop(&mut self) {
+        for &(_, fd) in &self.fds {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
@@ -2,7 +2,8 @@
 use crate::futex;
 use crate::shm::Shm;
 use crate::Result;
-use std::io::{Read, Write};
+use std::io::{IoSlice, IoSliceMut, Read, Write};
+use std::ops::Range;
 use std::{cmp, intrinsics, io, mem};
 
 #[derive(Debug)]
@@ -40,24 +41,37 @@ impl Header {
         }
     }
 
-    fn reader_wait(&mut self, #[allow(unused_variables)] expect_tail: u32) {
+    fn reader_wait(&mut self, #[allow(unused_variables)] expect_tail: u32) -> io::Result<()> {
         #[cfg(feature = "ring-futex")]
-        futex::futex_wait(&self.tail, expect_tail).expect("futex::futex_wait");
+        wait(&self.tail, expect_tail)?;
+        Ok(())
     }
 
-    fn writer_wait(&mut self, #[allow(unused_variables)] expect_head: u32) {
+    fn writer_wait(&mut self, #[allow(unused_variables)] expect_head: u32) -> io::Result<()> {
         #[cfg(feature = "ring-futex")]
-        futex::futex_wait(&self.head, expect_head).expect("futex::futex_wait");
+        wait(&self.head, expect_head)?;
+        Ok(())
     }
 
-    fn reader_notify(&mut self) {
+    fn reader_notify(&mut self) -> io::Result<()> {
         #[cfg(feature = "ring-futex")]
-        futex::futex_wake(&self.tail, 1).expect("futex::futex_wake");
+        futex::futex_wake(&self.tail, 1)?;
+        Ok(())
     }
 
-    fn writer_notify(&mut self) {
+    fn writer_notify(&mut self) -> io::Result<()> {
         #[cfg(feature = "ring-futex")]
-        futex::futex_wake(&self.head, 1).expect("futex::futex_wake");
+        futex::futex_wake(&self.head, 1)?;
+        Ok(())
+    }
+}
+
+// 被信号打断时直接返回，由调用方重新检查
+#[cfg(feature = "ring-futex")]
+fn wait(word: &u32, val: u32) -> io::Result<()> {
+    match futex::futex_wait(word, val) {
+        Err(err) if err.as_errno() != Some(libc::EINTR) => Err(err.into()),
+        _ => Ok(()),
     }
 }
 
@@ -97,89 +111,213 @@ impl Buffer {
         }
         Ok(buf)
     }
+
+    /// Unmaps the ring and, if this end created it, unlinks it, reporting
+    /// the errors that dropping would only log.
+    pub fn close(self) -> Result<()> {
+        self.0.close()
+    }
+}
+
+impl Buffer {
+    /// Splits `len` bytes starting at `start` into the parts before and after
+    /// the wrap boundary.
+    fn regions(start: usize, len: usize, size: usize) -> (Range<usize>, Range<usize>) {
+        let first = cmp::min(len, size - start);
+        (start..start + first, 0..len - first)
+    }
+
+    /// Copies everything available into `bufs`, in order and across the wrap
+    /// boundary, without waking the writer.
+    fn pop(&mut self, bufs: &mut [IoSliceMut<'_>]) -> usize {
+        let want = bufs.iter().map(|buf| buf.len()).sum::<usize>();
+        let size = self.header().size as usize;
+        let head = self.header().head() as usize;
+        let tail = self.header().tail() as usize;
+        let need_copy = cmp::min((tail + size - head) % size, want);
+        if need_copy == 0 {
+            return 0;
+        }
+        let (first, second) = Self::regions(head, need_copy, size);
+        let data = self.data();
+        let mut src = [&data[first], &data[second]];
+        let mut i = 0;
+        for buf in bufs.iter_mut() {
+            let mut buf = &mut buf[..];
+            while !buf.is_empty() && i < src.len() {
+                let n = cmp::min(buf.len(), src[i].len());
+                buf[..n].copy_from_slice(&src[i][..n]);
+                buf = &mut buf[n..];
+                src[i] = &src[i][n..];
+                if src[i].is_empty() {
+                    i += 1;
+                }
+            }
+        }
+        self.header_mut()
+            .set_head(((head + need_copy) % size) as u32);
+        need_copy
+    }
+
+    /// Copies as much of `bufs` as fits, without waking the reader.
+    fn push(&mut self, bufs: &[IoSlice<'_>]) -> usize {
+        let want = bufs.iter().map(|buf| buf.len()).sum::<usize>();
+        let size = self.header().size as usize;
+        let head = self.header().head() as usize;
+        let tail = self.header().tail() as usize;
+        // 保留一个字节区分空与满
+        let need_copy = cmp::min((head + size - tail - 1) % size, want);
+        if need_copy == 0 {
+            return 0;
+        }
+        let (first, second) = Self::regions(tail, need_copy, size);
+        let (low, high) = self.data_mut().split_at_mut(first.start);
+        let mut dst = [&mut high[..first.len()], &mut low[second]];
+        let mut i = 0;
+        for buf in bufs {
+            let mut buf = &buf[..];
+            while !buf.is_empty() && i < dst.len() {
+                let n = cmp::min(buf.len(), dst[i].len());
+                let (done, rest) = mem::take(&mut dst[i]).split_at_mut(n);
+                done.copy_from_slice(&buf[..n]);
+                buf = &buf[n..];
+                dst[i] = rest;
+                if dst[i].is_empty() {
+                    i += 1;
+                }
+            }
+        }
+        self.header_mut()
+            .set_tail(((tail + need_copy) % size) as u32);
+        need_copy
+    }
+
+    /// Blocks until the ring is no longer empty, or spins once while retries
+    /// are left.
+    fn wait_readable(
+        &mut self,
+        #[allow(unused_variables)] retry_count: &mut usize,
+    ) -> io::Result<()> {
+        let tail = self.header().tail();
+        if self.header().head() != tail {
+            return Ok(());
+        }
+        #[cfg(feature = "ring-futex-retry")]
+        if *retry_count < Self::MAX_LOCK_RETRY_COUNT {
+            *retry_count += 1;
+            return Ok(());
+        }
+        self.header_mut().reader_wait(tail)
+    }
+
+    /// Blocks until the ring is no longer full, or spins once while retries
+    /// are left.
+    fn wait_writable(
+        &mut self,
+        #[allow(unused_variables)] retry_count: &mut usize,
+    ) -> io::Result<()> {
+        let head = self.header().head();
+        if (self.header().tail() + 1) % self.header().size != head {
+            return Ok(());
+        }
+        #[cfg(feature = "ring-futex-retry")]
+        if *retry_count < Self::MAX_LOCK_RETRY_COUNT {
+            *retry_count += 1;
+            return Ok(());
+        }
+        self.header_mut().writer_wait(head)
+    }
 }
 
 impl Read for Buffer {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        #[cfg(feature = "ring-futex-retry")]
+        self.read_vectored(&mut [IoSliceMut::new(buf)])
+    }
+
+    /// Fills `bufs` in order with everything available, wrapping around the
+    /// end of the ring within a single call.
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Ok(0);
+        }
         let mut retry_count = 0;
         loop {
-            let head = self.header().head();
-            let tail = self.header().tail();
-
-            return if head == tail {
-                #[cfg(feature = "ring-futex-retry")]
-                if retry_count < Self::MAX_LOCK_RETRY_COUNT {
-                    retry_count += 1;
-                    continue;
-                }
-                self.header_mut().reader_wait(tail);
+            let n = self.pop(bufs);
+            if n > 0 {
+                self.header_mut().writer_notify()?;
+                return Ok(n);
+            }
+            self.wait_readable(&mut retry_count)?;
+        }
+    }
+
+    /// Wakes the writer only before blocking and once at the end, not after
+    /// every chunk.
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> io::Result<()> {
+        let mut retry_count = 0;
+        let mut freed = false;
+        while !buf.is_empty() {
+            let n = self.pop(&mut [IoSliceMut::new(buf)]);
+            if n > 0 {
+                buf = &mut mem::take(&mut buf)[n..];
+                freed = true;
                 continue;
-            } else if head < tail {
-                let need_copy = cmp::min(buf.len(), (tail - head) as usize);
-                (&mut buf[..need_copy]).write(&self.data()[head as usize..])?;
-                self.header_mut().set_head(head + need_copy as u32);
-                self.header_mut().writer_notify();
-                Ok(need_copy)
-            } else {
-                let size = self.header().size;
-                let need_copy = cmp::min((tail + size - head) as usize, buf.len());
-                if need_copy <= (size - head) as usize {
-                    (&mut buf[..need_copy]).write(&self.data_mut()[head as usize..])?;
-                    self.header_mut().set_head(head + need_copy as u32);
-                } else {
-                    let first_write = (size - head) as usize;
-                    (&mut buf[..first_write]).write(&self.data_mut()[head as usize..])?;
-                    let left = need_copy - first_write;
-                    (&mut buf[first_write..]).write(&self.data_mut()[..left])?;
-                    self.header_mut().set_head(left as _);
-                }
-                self.header_mut().writer_notify();
-                Ok(need_copy)
-            };
+            }
+            if mem::take(&mut freed) {
+                self.header_mut().writer_notify()?;
+            }
+            self.wait_readable(&mut retry_count)?;
         }
+        if freed {
+            self.header_mut().writer_notify()?;
+        }
+        Ok(())
     }
 }
 
 impl Write for Buffer {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        #[cfg(feature = "ring-futex-retry")]
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    /// Copies as much of `bufs` as fits, in order, wrapping around the end of
+    /// the ring within a single call.
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Ok(0);
+        }
         let mut retry_count = 0;
         loop {
-            let head = self.header().head();
-            let tail = self.header().tail();
-
-            return if head > 0 && tail == head - 1 {
-                #[cfg(feature = "ring-futex-retry")]
-                if retry_count < Self::MAX_LOCK_RETRY_COUNT {
-                    retry_count += 1;
-                    continue;
-                }
-                self.header_mut().writer_wait(head);
+            let n = self.push(bufs);
+            if n > 0 {
+                self.header_mut().reader_notify()?;
+                return Ok(n);
+            }
+            self.wait_writable(&mut retry_count)?;
+        }
+    }
+
+    /// Wakes the reader only before blocking and once at the end, not after
+    /// every chunk.
+    fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        let mut retry_count = 0;
+        let mut filled = false;
+        while !buf.is_empty() {
+            let n = self.push(&[IoSlice::new(buf)]);
+            if n > 0 {
+                buf = &buf[n..];
+                filled = true;
                 continue;
-            } else if tail < head {
-                let need_copy = cmp::min(buf.len(), (head - tail - 1) as usize);
-                (&mut self.data_mut()[tail as usize..]).write(&buf[..need_copy])?;
-                self.header_mut().set_tail(tail + need_copy as u32);
-                self.header_mut().reader_notify();
-                Ok(need_copy)
-            } else {
-                let size = self.header().size;
-                let need_copy = cmp::min(buf.len(), (size + head - 1 - tail) as usize);
-                if need_copy <= (size - tail) as usize {
-                    (&mut self.data_mut()[tail as usize..]).write(&buf[..need_copy])?;
-                    self.header_mut().set_tail(tail + need_copy as u32);
-                } else {
-                    let first_copy = (size - tail) as usize;
-                    (&mut self.data_mut()[tail as usize..]).write(&buf[..first_copy])?;
-                    let left = need_copy - first_copy;
-                    (&mut self.data_mut()[..left]).write(&buf[first_copy..])?;
-                    self.header_mut().set_tail(left as _);
-                }
-                self.header_mut().reader_notify();
-                Ok(need_copy)
-            };
+            }
+            if mem::take(&mut filled) {
+                self.header_mut().reader_notify()?;
+            }
+            self.wait_writable(&mut retry_count)?;
+        }
+        if filled {
+            self.header_mut().reader_notify()?;
         }
+        Ok(())
     }
 
     fn flush(&mut self) -> io::Result<()> {
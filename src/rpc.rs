@@ -0,0 +1,519 @@
+//! Request/response calls between processes over any pair of transports.
+//!
+//! A [`Client`] tags every request with an id and matches replies to
+//! callers by it, so any number of threads can have calls in flight on one
+//! connection. A [`Server`] dispatches requests to handlers by method name,
+//! either inline or on a pool of worker threads.
+//!
+//! Requests and replies travel over separate transports, e.g. two seqpacket
+//! socket pairs or two shared-memory rings; see [`endpoints`].
+
+use crate::marshal::Marshal;
+use crate::process::panic_message;
+use crate::transport::{BoxedReceiver, BoxedSender, Transport};
+use crate::{Error, Result};
+use log::error;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+use std::{cmp, io, mem, thread};
+
+/// The largest request or reply sent unless the transport's limit is lower.
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+const KIND_CALL: u8 = 0;
+const KIND_CANCEL: u8 = 1;
+
+const STATUS_OK: u8 = 0;
+const STATUS_ERR: u8 = 1;
+
+// 请求：kind u8 + id u64 [+ method + payload]；回复：id u64 + status u8 + payload
+const REPLY_HEADER_SIZE: usize = mem::size_of::<u64>() + 1;
+
+/// One end of a connection: where it sends, and where it receives.
+pub type Endpoint = (BoxedSender, BoxedReceiver);
+
+/// Creates the client and server endpoints of a connection, carrying
+/// requests over a pair of `requests` and replies over a pair of `replies`.
+/// Create them before `fork` and keep one endpoint on each side.
+pub fn endpoints(requests: &Transport, replies: &Transport) -> Result<(Endpoint, Endpoint)> {
+    let (request_sender, request_receiver) = requests.pair()?;
+    let (reply_sender, reply_receiver) = replies.pair()?;
+    Ok((
+        (request_sender, reply_receiver),
+        (reply_sender, request_receiver),
+    ))
+}
+
+fn message_limit(max: Option<usize>) -> usize {
+    max.map_or(MAX_MESSAGE_SIZE, |max| cmp::min(max, MAX_MESSAGE_SIZE))
+}
+
+fn read_id(bytes: &mut &[u8]) -> Result<u64> {
+    u64::unmarshal(bytes)
+}
+
+fn invalid(msg: &'static str) -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg).into()
+}
+
+enum Outcome {
+    Ok(Vec<u8>),
+    Err(String),
+}
+
+/// The sending side of either end, shared by the threads that send on it.
+struct Outbox {
+    sender: Mutex<BoxedSender>,
+    max_size: usize,
+}
+
+impl Outbox {
+    fn new(sender: BoxedSender) -> Outbox {
+        Outbox {
+            max_size: message_limit(sender.max_message_size()),
+            sender: Mutex::new(sender),
+        }
+    }
+
+    fn send(&self, msg: &[u8]) -> Result<()> {
+        if msg.len() > self.max_size {
+            return Err(Error::MessageTooLarge { max: self.max_size });
+        }
+        self.sender.lock().unwrap().send(msg)
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    calls: HashMap<u64, mpsc::Sender<Outcome>>,
+    disconnected: bool,
+}
+
+/// The calling side of a connection. Calls may be made from any number of
+/// threads at once.
+///
+/// A background thread receives the replies. It exits once the server
+/// closes its reply transport; over shared-memory rings, which never report
+/// a closed peer, it lives as long as the process.
+pub struct Client {
+    outbox: Outbox,
+    // 回复线程只持有这一份，客户端丢弃时发送端随之关闭
+    pending: Arc<Mutex<Pending>>,
+    next_id: AtomicU64,
+}
+
+impl Client {
+    pub fn new(sender: BoxedSender, receiver: BoxedReceiver) -> Result<Client> {
+        let pending = Arc::new(Mutex::new(Pending::default()));
+        let reader = pending.clone();
+        thread::Builder::new()
+            .name("ipc-rpc-client".to_string())
+            .spawn(move || read_replies(&reader, receiver))?;
+        Ok(Client {
+            outbox: Outbox::new(sender),
+            pending,
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Sends a request without waiting for the reply.
+    pub fn start(&self, method: &str, payload: &[u8]) -> Result<PendingCall<'_>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut msg = vec![KIND_CALL];
+        id.marshal(&mut msg);
+        method.to_string().marshal(&mut msg);
+        msg.extend_from_slice(payload);
+
+        let (tx, rx) = mpsc::channel();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.disconnected {
+                return Err(Error::Disconnected);
+            }
+            pending.calls.insert(id, tx);
+        }
+        if let Err(err) = self.outbox.send(&msg) {
+            self.pending.lock().unwrap().calls.remove(&id);
+            return Err(err);
+        }
+        Ok(PendingCall {
+            client: self,
+            id,
+            reply: rx,
+            finished: false,
+        })
+    }
+
+    /// Calls `method` and waits for its reply, for at most `timeout` if
+    /// given. A call that times out is cancelled.
+    pub fn call(&self, method: &str, payload: &[u8], timeout: Option<Duration>) -> Result<Vec<u8>> {
+        let call = self.start(method, payload)?;
+        match timeout {
+            Some(timeout) => call.wait_timeout(timeout),
+            None => call.wait(),
+        }
+    }
+
+    /// Like [`call`](Self::call), marshalling the request and the reply.
+    /// With the `serde` feature, serde types go through
+    /// [`Serde`](crate::marshal::Serde).
+    pub fn call_typed<Req, Resp>(
+        &self,
+        method: &str,
+        request: &Req,
+        timeout: Option<Duration>,
+    ) -> Result<Resp>
+    where
+        Req: Marshal,
+        Resp: Marshal,
+    {
+        let mut payload = Vec::new();
+        request.marshal(&mut payload);
+        let reply = self.call(method, &payload, timeout)?;
+        Resp::unmarshal(&mut &reply[..])
+    }
+
+    fn cancel(&self, id: u64) -> Result<()> {
+        // 回复可能已经在路上，服务端收到取消时会忽略已完成的调用
+        if self.pending.lock().unwrap().calls.remove(&id).is_none() {
+            return Ok(());
+        }
+        let mut msg = vec![KIND_CANCEL];
+        id.marshal(&mut msg);
+        self.outbox.send(&msg)
+    }
+}
+
+fn read_replies(pending: &Mutex<Pending>, mut receiver: BoxedReceiver) {
+    let max_size = message_limit(receiver.max_message_size());
+    let mut buf = vec![0u8; max_size];
+    loop {
+        // 超限的回复只剩开头，但 id 还在，照样能交给调用方
+        let (n, too_large) = match receiver.recv(&mut buf) {
+            Ok(Some(n)) => (n, false),
+            Ok(None) => break,
+            Err(Error::MessageTooLarge { .. }) => (max_size, true),
+            Err(err) => {
+                error!("rpc client: {}", err);
+                break;
+            }
+        };
+        let mut bytes = &buf[..n];
+        let (id, status) = match (read_id(&mut bytes), u8::unmarshal(&mut bytes)) {
+            (Ok(id), Ok(status)) => (id, status),
+            _ => {
+                error!("rpc client: {}", invalid("truncated reply"));
+                continue;
+            }
+        };
+        let outcome = if too_large {
+            Outcome::Err(Error::MessageTooLarge { max: max_size }.to_string())
+        } else if status == STATUS_OK {
+            Outcome::Ok(bytes.to_vec())
+        } else {
+            Outcome::Err(String::from_utf8_lossy(bytes).into_owned())
+        };
+        // 调用方已取消或超时的回复直接丢弃
+        if let Some(tx) = pending.lock().unwrap().calls.remove(&id) {
+            let _ = tx.send(outcome);
+        }
+    }
+    let mut pending = pending.lock().unwrap();
+    pending.disconnected = true;
+    pending.calls.clear();
+}
+
+/// A call whose reply hasn't been collected yet. Dropping it cancels the
+/// call.
+pub struct PendingCall<'a> {
+    client: &'a Client,
+    id: u64,
+    reply: mpsc::Receiver<Outcome>,
+    finished: bool,
+}
+
+impl PendingCall<'_> {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Waits for the reply. A handler's error comes back as
+    /// [`Error::Remote`].
+    pub fn wait(mut self) -> Result<Vec<u8>> {
+        let outcome = self.reply.recv();
+        self.finished = true;
+        match outcome {
+            Ok(outcome) => outcome.into_result(),
+            Err(mpsc::RecvError) => Err(Error::Disconnected),
+        }
+    }
+
+    /// Waits at most `timeout` for the reply, then cancels the call and
+    /// fails with [`Error::TimedOut`].
+    pub fn wait_timeout(mut self, timeout: Duration) -> Result<Vec<u8>> {
+        let outcome = self.reply.recv_timeout(timeout);
+        self.finished = true;
+        match outcome {
+            Ok(outcome) => outcome.into_result(),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // 取消没发出去也还是超时，不能把超时错误换掉
+                if let Err(err) = self.client.cancel(self.id) {
+                    error!("rpc client: {}", err);
+                }
+                Err(Error::TimedOut)
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(Error::Disconnected),
+        }
+    }
+
+    /// Gives up on the call. The server skips it if it hasn't started, and
+    /// handlers that poll [`Request::is_cancelled`] can stop early.
+    pub fn cancel(mut self) -> Result<()> {
+        self.finished = true;
+        self.client.cancel(self.id)
+    }
+}
+
+impl Drop for PendingCall<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Err(err) = self.client.cancel(self.id) {
+                error!("{}", err);
+            }
+        }
+    }
+}
+
+impl Outcome {
+    fn into_result(self) -> Result<Vec<u8>> {
+        match self {
+            Outcome::Ok(reply) => Ok(reply),
+            Outcome::Err(msg) => Err(Error::Remote(msg)),
+        }
+    }
+}
+
+/// A request as seen by a handler.
+pub struct Request<'a> {
+    id: u64,
+    method: &'a str,
+    payload: &'a [u8],
+    cancelled: &'a AtomicBool,
+}
+
+impl Request<'_> {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn method(&self) -> &str {
+        self.method
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        self.payload
+    }
+
+    pub fn decode<T: Marshal>(&self) -> Result<T> {
+        T::unmarshal(&mut &self.payload[..])
+    }
+
+    /// Whether the client has given up on this call; its reply, if any,
+    /// will be discarded.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+type Handler = Box<dyn Fn(&Request) -> std::result::Result<Vec<u8>, String> + Send + Sync>;
+
+struct Job {
+    id: u64,
+    method: String,
+    payload: Vec<u8>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// The serving side: a table of handlers by method name.
+#[derive(Default)]
+pub struct Server {
+    handlers: HashMap<String, Handler>,
+    workers: usize,
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server::default()
+    }
+
+    /// Registers `handler` for `method`. An `Err` or a panic is sent back to
+    /// the caller as [`Error::Remote`].
+    pub fn handle<F>(&mut self, method: &str, handler: F) -> &mut Server
+    where
+        F: Fn(&Request) -> std::result::Result<Vec<u8>, String> + Send + Sync + 'static,
+    {
+        self.handlers.insert(method.to_string(), Box::new(handler));
+        self
+    }
+
+    /// Registers a handler that takes and returns marshalled values.
+    pub fn handle_typed<Req, Resp, F>(&mut self, method: &str, handler: F) -> &mut Server
+    where
+        Req: Marshal,
+        Resp: Marshal,
+        F: Fn(Req) -> Resp + Send + Sync + 'static,
+    {
+        self.handle(method, move |request| {
+            let request = request.decode::<Req>().map_err(|err| err.to_string())?;
+            let mut reply = Vec::new();
+            handler(request).marshal(&mut reply);
+            Ok(reply)
+        })
+    }
+
+    /// Runs handlers on `workers` threads. With none, the default, each
+    /// request is handled on the serving thread before the next is read:
+    /// the lowest latency, but one call at a time, and cancellation only
+    /// reaches calls that haven't started.
+    pub fn workers(&mut self, workers: usize) -> &mut Server {
+        self.workers = workers;
+        self
+    }
+
+    /// Serves requests until the client side closes the request transport.
+    pub fn serve(&self, sender: BoxedSender, mut receiver: BoxedReceiver) -> Result<()> {
+        let outbox = Outbox::new(sender);
+        let in_flight = Mutex::new(HashMap::<u64, Arc<AtomicBool>>::new());
+        let request_limit = message_limit(receiver.max_message_size());
+        let mut buf = vec![0u8; request_limit];
+
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Mutex::new(queue);
+        thread::scope(|scope| {
+            for _ in 0..self.workers {
+                scope.spawn(|| loop {
+                    // 取到任务就放开锁，别的工作线程才能接着取
+                    let job = queue.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => self.run(&outbox, &in_flight, job),
+                        Err(_) => break,
+                    }
+                });
+            }
+
+            let result = loop {
+                let (n, too_large) = match receiver.recv(&mut buf) {
+                    Ok(Some(n)) => (n, false),
+                    Ok(None) => break Ok(()),
+                    Err(Error::MessageTooLarge { .. }) => (request_limit, true),
+                    Err(err) => break Err(err),
+                };
+                let mut bytes = &buf[..n];
+                let (kind, id) = match (u8::unmarshal(&mut bytes), read_id(&mut bytes)) {
+                    (Ok(kind), Ok(id)) => (kind, id),
+                    _ => {
+                        error!("rpc server: {}", invalid("truncated request"));
+                        continue;
+                    }
+                };
+                if kind == KIND_CANCEL {
+                    if let Some(cancelled) = in_flight.lock().unwrap().get(&id) {
+                        cancelled.store(true, Ordering::Relaxed);
+                    }
+                    continue;
+                }
+                if too_large {
+                    let err = Error::MessageTooLarge { max: request_limit };
+                    reply(&outbox, id, Err(err.to_string()));
+                    continue;
+                }
+                let method = match String::unmarshal(&mut bytes) {
+                    Ok(method) => method,
+                    Err(err) => {
+                        reply(&outbox, id, Err(err.to_string()));
+                        continue;
+                    }
+                };
+                let cancelled = Arc::new(AtomicBool::new(false));
+                in_flight.lock().unwrap().insert(id, cancelled.clone());
+                let job = Job {
+                    id,
+                    method,
+                    payload: bytes.to_vec(),
+                    cancelled,
+                };
+                if self.workers == 0 {
+                    self.run(&outbox, &in_flight, job);
+                } else if jobs.send(job).is_err() {
+                    break Err(
+                        io::Error::new(io::ErrorKind::BrokenPipe, "rpc workers exited").into(),
+                    );
+                }
+            };
+            // 关闭队列，等工作线程处理完已收到的请求
+            drop(jobs);
+            result
+        })
+    }
+
+    fn run(&self, outbox: &Outbox, in_flight: &Mutex<HashMap<u64, Arc<AtomicBool>>>, job: Job) {
+        let result = if job.cancelled.load(Ordering::Relaxed) {
+            None
+        } else {
+            let request = Request {
+                id: job.id,
+                method: &job.method,
+                payload: &job.payload,
+                cancelled: &job.cancelled,
+            };
+            Some(match self.handlers.get(&job.method) {
+                // 处理函数 panic 不能带走工作线程，当作错误回给调用方
+                Some(handler) => panic::catch_unwind(AssertUnwindSafe(|| handler(&request)))
+                    .unwrap_or_else(|payload| {
+                        let msg = format!(
+                            "handler for {} panicked: {}",
+                            job.method,
+                            panic_message(&*payload)
+                        );
+                        error!("rpc server: {}", msg);
+                        Err(msg)
+                    }),
+                None => Err(format!("no handler for method {}", job.method)),
+            })
+        };
+        in_flight.lock().unwrap().remove(&job.id);
+        match result {
+            Some(result) if !job.cancelled.load(Ordering::Relaxed) => reply(outbox, job.id, result),
+            _ => {}
+        }
+    }
+}
+
+fn reply(outbox: &Outbox, id: u64, result: std::result::Result<Vec<u8>, String>) {
+    let mut msg = Vec::with_capacity(REPLY_HEADER_SIZE);
+    id.marshal(&mut msg);
+    match result {
+        Ok(payload) => {
+            msg.push(STATUS_OK);
+            msg.extend_from_slice(&payload);
+        }
+        Err(err) => {
+            msg.push(STATUS_ERR);
+            msg.extend_from_slice(err.as_bytes());
+        }
+    }
+    if msg.len() > outbox.max_size {
+        msg.truncate(REPLY_HEADER_SIZE - 1);
+        msg.push(STATUS_ERR);
+        let err = Error::MessageTooLarge {
+            max: outbox.max_size,
+        };
+        msg.extend_from_slice(err.to_string().as_bytes());
+    }
+    if let Err(err) = outbox.send(&msg) {
+        error!("rpc server: {}", err);
+    }
+}
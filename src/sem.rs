@@ -1,80 +1,197 @@
-#![allow(dead_code)]
-
+use crate::flags::{Mode, OpenFlags};
+#[cfg(not(target_os = "android"))]
+use crate::shm::Shm;
 use crate::{errors::libc_errno, Result};
-use core::fmt::Debug;
+use core::fmt::{self, Debug, Formatter};
 use libc::c_uint;
+use log::error;
+use std::cell::UnsafeCell;
 use std::ffi::CString;
-use std::mem;
-use std::mem::MaybeUninit;
+use std::marker::PhantomPinned;
+use std::pin::Pin;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{mem, ptr};
 
 pub trait SemaphoreLike: Debug {
-    fn value(&self) -> usize;
-    fn post(&self);
-    fn wait(&self);
+    fn value(&self) -> Result<usize>;
+    fn post(&self) -> Result<()>;
+    fn wait(&self) -> Result<()>;
+    /// Returns `false` instead of blocking if the semaphore is zero.
+    fn try_wait(&self) -> Result<bool>;
+    /// Returns `false` if the semaphore could not be decremented before
+    /// `timeout` elapsed. The deadline is measured on `CLOCK_REALTIME`.
+    fn wait_timeout(&self, timeout: Duration) -> Result<bool>;
+
+    /// Waits for one permit and returns a guard that posts it back on drop.
+    fn acquire(&self) -> Result<SemaphorePermit<'_, Self>> {
+        self.wait()?;
+        Ok(SemaphorePermit::new(self, 1))
+    }
+
+    fn try_acquire(&self) -> Result<Option<SemaphorePermit<'_, Self>>> {
+        Ok(self.try_wait()?.then(|| SemaphorePermit::new(self, 1)))
+    }
+
+    fn acquire_timeout(&self, timeout: Duration) -> Result<Option<SemaphorePermit<'_, Self>>> {
+        Ok(self
+            .wait_timeout(timeout)?
+            .then(|| SemaphorePermit::new(self, 1)))
+    }
+
+    /// Waits for `n` permits, one at a time. POSIX semaphores can't take
+    /// several permits atomically, so two callers racing for the last permits
+    /// can each hold a part; permits taken before an error are posted back.
+    fn acquire_many(&self, n: usize) -> Result<SemaphorePermit<'_, Self>> {
+        let mut permit = SemaphorePermit::new(self, 0);
+        while permit.permits < n {
+            self.wait()?;
+            permit.permits += 1;
+        }
+        Ok(permit)
+    }
+
+    /// Like [`acquire_many`](Self::acquire_many), giving up once `timeout`
+    /// has elapsed; the permits taken by then are posted back.
+    fn acquire_many_timeout(
+        &self,
+        n: usize,
+        timeout: Duration,
+    ) -> Result<Option<SemaphorePermit<'_, Self>>> {
+        let deadline = Instant::now() + timeout;
+        let mut permit = SemaphorePermit::new(self, 0);
+        while permit.permits < n {
+            let left = deadline.saturating_duration_since(Instant::now());
+            // 超时返回时 permit 被丢弃，已拿到的会还回去
+            if !self.wait_timeout(left)? {
+                return Ok(None);
+            }
+            permit.permits += 1;
+        }
+        Ok(Some(permit))
+    }
+}
+
+/// Permits taken from a semaphore, posted back when dropped.
+#[derive(Debug)]
+pub struct SemaphorePermit<'a, S: SemaphoreLike + ?Sized> {
+    sem: &'a S,
+    permits: usize,
+}
+
+impl<'a, S: SemaphoreLike + ?Sized> SemaphorePermit<'a, S> {
+    fn new(sem: &'a S, permits: usize) -> Self {
+        SemaphorePermit { sem, permits }
+    }
+
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Keeps the permits taken: nothing is posted back.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl<S: SemaphoreLike + ?Sized> Drop for SemaphorePermit<'_, S> {
+    fn drop(&mut self) {
+        for _ in 0..self.permits {
+            if let Err(err) = self.sem.post() {
+                error!("SemaphorePermit: {}", err);
+                break;
+            }
+        }
+    }
 }
 
 impl SemaphoreLike for *mut libc::sem_t {
-    fn value(&self) -> usize {
+    fn value(&self) -> Result<usize> {
         unsafe {
-            let mut val: libc::c_int = MaybeUninit::uninit().assume_init();
+            let mut val: libc::c_int = 0;
             if libc::sem_getvalue(*self, &mut val) == -1 {
-                panic_errno!("sem_getvalue");
+                return_errno!("sem_getvalue");
             }
-            val as _
+            Ok(val as _)
         }
     }
 
-    fn post(&self) {
+    fn post(&self) -> Result<()> {
         unsafe {
             if libc::sem_post(*self) == -1 {
-                panic_errno!("sem_post");
+                return_errno!("sem_post");
             }
+            Ok(())
         }
     }
 
-    fn wait(&self) {
+    fn wait(&self) -> Result<()> {
         unsafe {
             while libc::sem_wait(*self) == -1 {
                 if libc_errno() == libc::EINTR {
                     continue;
                 }
-                panic_errno!("sem_wait");
+                return_errno!("sem_wait");
             }
+            Ok(())
         }
     }
-}
 
-#[derive(Debug)]
-pub enum Semaphore {
-    Anonymous(libc::sem_t),
-    Named(*mut libc::sem_t, String),
-}
+    fn try_wait(&self) -> Result<bool> {
+        unsafe {
+            while libc::sem_trywait(*self) == -1 {
+                match libc_errno() {
+                    libc::EINTR => continue,
+                    libc::EAGAIN => return Ok(false),
+                    _ => return_errno!("sem_trywait"),
+                }
+            }
+            Ok(true)
+        }
+    }
 
-impl Semaphore {
-    pub fn init(val: usize) -> Result<Semaphore> {
+    fn wait_timeout(&self, timeout: Duration) -> Result<bool> {
+        let deadline = SystemTime::now() + timeout;
+        let deadline = deadline.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let deadline = libc::timespec {
+            tv_sec: deadline.as_secs() as _,
+            tv_nsec: deadline.subsec_nanos() as _,
+        };
         unsafe {
-            let sem = Semaphore::Anonymous(MaybeUninit::uninit().assume_init());
-            if libc::sem_init(sem.as_ptr_mut(), 1, val as _) == -1 {
-                return_errno!("sem_init");
+            while libc::sem_timedwait(*self, &deadline) == -1 {
+                match libc_errno() {
+                    libc::EINTR => continue,
+                    libc::ETIMEDOUT => return Ok(false),
+                    _ => return_errno!("sem_timedwait"),
+                }
             }
-            Ok(sem)
+            Ok(true)
         }
     }
+}
 
-    pub fn open(name: &str, flags: isize, mode: isize, value: usize) -> Result<Semaphore> {
+/// A named POSIX semaphore.
+#[derive(Debug)]
+pub struct Semaphore {
+    inner: *mut libc::sem_t,
+    name: String,
+}
+
+unsafe impl Send for Semaphore {}
+unsafe impl Sync for Semaphore {}
+
+impl Semaphore {
+    pub fn open(name: &str, flags: OpenFlags, mode: Mode, value: usize) -> Result<Semaphore> {
         unsafe {
             let c_name = CString::new(name)?;
-            let sem = libc::sem_open(
-                c_name.as_ptr(),
-                flags as _,
-                mode as libc::mode_t,
-                value as c_uint,
-            );
+            let sem = libc::sem_open(c_name.as_ptr(), flags.bits(), mode.bits(), value as c_uint);
 
             if sem == libc::SEM_FAILED {
-                return_errno!();
+                return_errno!("sem_open", name);
             }
-            Ok(Semaphore::Named(sem, name.to_string()))
+            Ok(Semaphore {
+                inner: sem,
+                name: name.to_string(),
+            })
         }
     }
 
@@ -82,100 +199,161 @@ impl Semaphore {
         unsafe {
             let c_name = CString::new(name)?;
             if libc::sem_unlink(c_name.as_ptr()) == -1 {
-                return_errno!("sem_unlink");
+                return_errno!("sem_unlink", name);
             }
             Ok(())
         }
     }
 
     pub fn unlink_self(self) {
-        if let Semaphore::Named(_, ref name) = self {
-            let _ = Self::unlink(name);
-        }
+        let _ = Self::unlink(&self.name);
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Closes the semaphore, reporting the error that dropping would only log.
+    pub fn close(mut self) -> Result<()> {
+        self.release()
     }
 
-    fn as_ptr_mut(&self) -> *mut libc::sem_t {
-        match self {
-            &Semaphore::Anonymous(ref sem) => unsafe { mem::transmute(sem) },
-            &Semaphore::Named(sem, ..) => sem,
+    pub(crate) fn release(&mut self) -> Result<()> {
+        if self.inner.is_null() {
+            return Ok(());
         }
+        let sem = mem::replace(&mut self.inner, ptr::null_mut());
+        unsafe {
+            if libc::sem_close(sem) == -1 {
+                return_errno!("sem_close", &self.name);
+            }
+        }
+        Ok(())
     }
 }
 
 impl SemaphoreLike for Semaphore {
-    fn value(&self) -> usize {
-        self.as_ptr_mut().value()
+    fn value(&self) -> Result<usize> {
+        self.inner.value()
+    }
+
+    fn post(&self) -> Result<()> {
+        self.inner.post()
+    }
+
+    fn wait(&self) -> Result<()> {
+        self.inner.wait()
     }
 
-    fn post(&self) {
-        self.as_ptr_mut().post()
+    fn try_wait(&self) -> Result<bool> {
+        self.inner.try_wait()
     }
 
-    fn wait(&self) {
-        self.as_ptr_mut().wait()
+    fn wait_timeout(&self, timeout: Duration) -> Result<bool> {
+        self.inner.wait_timeout(timeout)
     }
 }
 
 impl Drop for Semaphore {
     fn drop(&mut self) {
-        match self {
-            &mut Semaphore::Anonymous(..) => unsafe {
-                assert_ne!(libc::sem_destroy(self.as_ptr_mut()), -1);
-            },
-
-            &mut Semaphore::Named(..) => unsafe {
-                assert_ne!(libc::sem_close(self.as_ptr_mut()), -1);
-            },
+        if let Err(err) = self.release() {
+            error!("{}", err);
         }
     }
 }
 
-impl From<*const u8> for &Semaphore {
-    fn from(ptr: *const u8) -> Self {
-        unsafe { &*(ptr as *const Semaphore) }
-    }
+/// An unnamed POSIX semaphore living inside a shared mapping.
+///
+/// It is initialized in place with `pshared = 1` and is only ever handed out
+/// as a pinned reference borrowed from the [`Shm`] it lives in, so it can
+/// neither move nor outlive the mapping.
+#[repr(transparent)]
+pub struct UnnamedSemaphore {
+    inner: UnsafeCell<libc::sem_t>,
+    _pin: PhantomPinned,
 }
 
-impl From<*mut u8> for &mut Semaphore {
-    fn from(ptr: *mut u8) -> Self {
-        unsafe { &mut *(ptr as *mut Semaphore) }
+unsafe impl Send for UnnamedSemaphore {}
+unsafe impl Sync for UnnamedSemaphore {}
+
+#[cfg(not(target_os = "android"))]
+unsafe impl crate::shm::ShmSafe for UnnamedSemaphore {}
+
+impl UnnamedSemaphore {
+    /// Runs `sem_init` on the bytes at `offset` in `shm`.
+    ///
+    /// # Safety
+    ///
+    /// No semaphore may already be initialized and in use at `offset`, in
+    /// this process or any other sharing the mapping.
+    #[cfg(not(target_os = "android"))]
+    pub unsafe fn init_in(
+        shm: &Shm,
+        offset: usize,
+        value: usize,
+    ) -> Result<Pin<&UnnamedSemaphore>> {
+        let sem = shm.slot::<UnnamedSemaphore>(offset)?;
+        if libc::sem_init((*sem).as_ptr(), 1, value as _) == -1 {
+            return_errno!("sem_init");
+        }
+        Ok(Pin::new_unchecked(&*sem))
     }
-}
 
-#[derive(Debug)]
-#[repr(transparent)]
-pub(crate) struct RawSemaphore(libc::sem_t);
+    /// Returns the semaphore another process initialized at `offset` in `shm`.
+    ///
+    /// # Safety
+    ///
+    /// A semaphore must have been initialized at `offset` with
+    /// [`init_in`](Self::init_in) and not yet destroyed.
+    #[cfg(not(target_os = "android"))]
+    pub unsafe fn attach(shm: &Shm, offset: usize) -> Result<Pin<&UnnamedSemaphore>> {
+        Ok(Pin::new_unchecked(&*shm.slot::<UnnamedSemaphore>(offset)?))
+    }
 
-impl RawSemaphore {
-    pub(crate) fn init(&self, val: usize) {
-        if unsafe { libc::sem_init(self.as_ptr_mut(), 1, val as _) } == -1 {
-            panic_errno!("sem_init");
+    /// Runs `sem_destroy`.
+    ///
+    /// # Safety
+    ///
+    /// No process may use the semaphore afterwards, and none may be blocked on
+    /// it.
+    pub unsafe fn destroy(self: Pin<&Self>) -> Result<()> {
+        if libc::sem_destroy(self.as_ptr()) == -1 {
+            return_errno!("sem_destroy");
         }
+        Ok(())
     }
 
-    fn as_ptr_mut(&self) -> *mut libc::sem_t {
-        unsafe { mem::transmute(&self.0) }
+    fn as_ptr(&self) -> *mut libc::sem_t {
+        self.inner.get()
     }
 }
 
-impl SemaphoreLike for RawSemaphore {
-    fn value(&self) -> usize {
-        self.as_ptr_mut().value()
+impl SemaphoreLike for UnnamedSemaphore {
+    fn value(&self) -> Result<usize> {
+        self.as_ptr().value()
     }
 
-    fn post(&self) {
-        self.as_ptr_mut().post()
+    fn post(&self) -> Result<()> {
+        self.as_ptr().post()
     }
 
-    fn wait(&self) {
-        self.as_ptr_mut().wait()
+    fn wait(&self) -> Result<()> {
+        self.as_ptr().wait()
+    }
+
+    fn try_wait(&self) -> Result<bool> {
+        self.as_ptr().try_wait()
+    }
+
+    fn wait_timeout(&self, timeout: Duration) -> Result<bool> {
+        self.as_ptr().wait_timeout(timeout)
     }
 }
 
-impl Drop for RawSemaphore {
-    fn drop(&mut self) {
-        unsafe {
-            assert_ne!(libc::sem_destroy(self.as_ptr_mut()), -1);
-        }
+impl Debug for UnnamedSemaphore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnnamedSemaphore")
+            .field("addr", &self.as_ptr())
+            .finish()
     }
 }
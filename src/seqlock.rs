@@ -0,0 +1,148 @@
+use crate::futex;
+use crate::shm::ShmSafe;
+use crate::Result;
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{self, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+use std::{fmt, hint, ptr};
+
+/// The latest value of some state, published by a writer and read by any
+/// number of processes without locking.
+///
+/// Place it in a [`Shm`](crate::shm::Shm) like [`SharedEvent`]. Readers copy
+/// the value and retry if a write overlapped the copy, so they never hold
+/// up the writer. Writers are serialized, but it is built for one. A writer
+/// that dies mid-write leaves readers spinning.
+///
+/// [`SharedEvent`]: crate::event::SharedEvent
+#[repr(C)]
+pub struct SharedSeqLock<T> {
+    // 奇数表示正在写；同时是 futex 字
+    seq: AtomicU32,
+    waiters: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ShmSafe + Copy> Sync for SharedSeqLock<T> {}
+unsafe impl<T: ShmSafe + Copy> ShmSafe for SharedSeqLock<T> {}
+
+impl<T: ShmSafe + Copy> SharedSeqLock<T> {
+    pub const fn new(value: T) -> SharedSeqLock<T> {
+        SharedSeqLock {
+            seq: AtomicU32::new(0),
+            waiters: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// The number of writes so far, wrapping at `u32::MAX / 2`.
+    pub fn version(&self) -> u32 {
+        self.seq.load(Ordering::Acquire) / 2
+    }
+
+    pub fn read(&self) -> T {
+        self.read_versioned().1
+    }
+
+    /// Reads the value along with the version it was written as.
+    pub fn read_versioned(&self) -> (u32, T) {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq % 2 == 1 {
+                hint::spin_loop();
+                continue;
+            }
+            let value = unsafe { ptr::read_volatile(self.value.get() as *const MaybeUninit<T>) };
+            atomic::fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == seq {
+                // 两次序号一致，读到的不是写了一半的值
+                return (seq / 2, unsafe { value.assume_init() });
+            }
+        }
+    }
+
+    /// Publishes `value` and wakes everyone waiting for a change.
+    pub fn write(&self, value: T) -> Result<()> {
+        let mut seq = self.seq.load(Ordering::Relaxed);
+        loop {
+            if seq % 2 == 1 {
+                hint::spin_loop();
+                seq = self.seq.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.seq.compare_exchange_weak(
+                seq,
+                seq.wrapping_add(1),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => seq = current,
+            }
+        }
+        atomic::fence(Ordering::Release);
+        unsafe { ptr::write_volatile(self.value.get(), value) };
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+
+        atomic::fence(Ordering::SeqCst);
+        if self.waiters.load(Ordering::Relaxed) > 0 {
+            futex::futex_wake(futex::as_word(&self.seq), i32::MAX as u32)?;
+        }
+        Ok(())
+    }
+
+    /// Waits until the version differs from `version`, then reads.
+    pub fn wait_changed(&self, version: u32) -> Result<(u32, T)> {
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let result = loop {
+            let seq = self.seq.load(Ordering::SeqCst);
+            if seq != version.wrapping_mul(2) {
+                break Ok(());
+            }
+            if let Err(err) = futex::futex_wait(futex::as_word(&self.seq), seq) {
+                break Err(err);
+            }
+        };
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        result.map(|_| self.read_versioned())
+    }
+
+    /// Returns `None` if the version was still `version` when `timeout`
+    /// elapsed.
+    pub fn wait_changed_timeout(
+        &self,
+        version: u32,
+        timeout: Duration,
+    ) -> Result<Option<(u32, T)>> {
+        let deadline = Instant::now() + timeout;
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let result = loop {
+            let seq = self.seq.load(Ordering::SeqCst);
+            if seq != version.wrapping_mul(2) {
+                break Ok(true);
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break Ok(false);
+            }
+            if let Err(err) = futex::futex_timed_wait(futex::as_word(&self.seq), seq, left) {
+                break Err(err);
+            }
+        };
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        match result {
+            Ok(true) => Ok(Some(self.read_versioned())),
+            Ok(false) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<T> fmt::Debug for SharedSeqLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedSeqLock")
+            .field("seq", &self.seq)
+            .finish()
+    }
+}
@@ -1,8 +1,31 @@
-#![allow(dead_code)]
-
-use crate::Result;
+use crate::errors::libc_errno;
+use crate::{Error, Result};
+use log::error;
 use std::ffi::CString;
-use std::{ptr, slice};
+use std::sync::atomic::{AtomicU32, AtomicU64};
+use std::{io, mem, ptr, slice};
+
+/// Marker for types that may live inside a shared mapping and be used from
+/// several processes at once.
+///
+/// # Safety
+///
+/// Implementors must be `#[repr(C)]`, contain no pointers or handles that are
+/// only meaningful inside one process, and must not rely on `Drop`.
+pub unsafe trait ShmSafe: Sync {}
+
+unsafe impl ShmSafe for AtomicU32 {}
+unsafe impl ShmSafe for AtomicU64 {}
+
+macro_rules! shm_safe_plain {
+    ($($t: ty),*) => {
+        $(unsafe impl ShmSafe for $t {})*
+    };
+}
+
+shm_safe_plain!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: ShmSafe, const N: usize> ShmSafe for [T; N] {}
 
 #[repr(C)]
 #[derive(Debug)]
@@ -18,20 +41,40 @@ unsafe impl Sync for Shm {}
 
 impl Shm {
     pub fn open(name: &str, size: usize, owner: bool) -> Result<Shm> {
+        let flags = if owner {
+            libc::O_RDWR | libc::O_CREAT | libc::O_EXCL
+        } else {
+            libc::O_RDWR
+        };
+        Self::map(name, size, flags, owner)
+    }
+
+    /// Opens `name`, creating it zero-filled if it doesn't exist. Nobody owns
+    /// the result: the name stays until [`unlink`](Self::unlink) removes it.
+    pub fn open_or_create(name: &str, size: usize) -> Result<Shm> {
+        Self::map(name, size, libc::O_RDWR | libc::O_CREAT, false)
+    }
+
+    pub fn unlink(name: &str) -> Result<()> {
+        unsafe {
+            let cstr = CString::new(name)?;
+            if libc::shm_unlink(cstr.as_ptr()) == -1 {
+                return_errno!("shm_unlink", name);
+            }
+        }
+        Ok(())
+    }
+
+    fn map(name: &str, size: usize, flags: libc::c_int, owner: bool) -> Result<Shm> {
         unsafe {
-            let cstr = CString::new(name).expect("CString::new");
-            let flags = if owner {
-                libc::O_RDWR | libc::O_CREAT | libc::O_EXCL
-            } else {
-                libc::O_RDWR
-            };
+            let cstr = CString::new(name)?;
             let shm_fd = libc::shm_open(cstr.as_ptr(), flags, 0o666);
             if shm_fd == -1 {
-                return_errno!("shm_open");
+                return_errno!("shm_open", name);
             }
             if libc::ftruncate64(shm_fd, size as _) == -1 {
                 libc::close(shm_fd);
-                return_errno!("ftruncate64");
+                return_errno!("ftruncate64", name);
             }
             let addr = libc::mmap(
                 ptr::null_mut::<libc::c_void>(),
@@ -43,18 +86,15 @@ impl Shm {
             );
             if addr == libc::MAP_FAILED {
                 libc::close(shm_fd);
-                return_errno!("mmap");
+                return_errno!("mmap", name);
             }
             if libc::close(shm_fd) == -1 {
-                if libc::munmap(addr, size as _) == -1 {
-                    panic_errno!("munmap");
-                }
+                let errno = libc_errno();
+                libc::munmap(addr, size as _);
                 if owner {
-                    if libc::shm_unlink(cstr.as_ptr()) == -1 {
-                        panic_errno!("shm_unlink");
-                    }
+                    libc::shm_unlink(cstr.as_ptr());
                 }
-                return_errno!("close");
+                return Err(Error::errno(errno, "close", Some(name)));
             }
             Ok(Shm {
                 addr: addr as _,
@@ -65,6 +105,77 @@ impl Shm {
         }
     }
 
+    /// Creates an anonymous shared mapping. It has no name in `/dev/shm`, but
+    /// is inherited by children created with `fork` and stays shared with them.
+    pub fn anonymous(size: usize) -> Result<Shm> {
+        unsafe {
+            let addr = libc::mmap(
+                ptr::null_mut::<libc::c_void>(),
+                size as _,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if addr == libc::MAP_FAILED {
+                return_errno!("mmap");
+            }
+            Ok(Shm {
+                addr: addr as _,
+                size,
+                owner: false,
+                name: String::new(),
+            })
+        }
+    }
+
+    /// Moves `value` into the mapping at `offset` and returns a reference to it.
+    ///
+    /// # Safety
+    ///
+    /// No reference to the bytes at `offset..offset + size_of::<T>()` may be
+    /// alive, in this process or any other, while the value is written.
+    pub unsafe fn place<T: ShmSafe>(&self, offset: usize, value: T) -> Result<&T> {
+        let ptr = self.slot::<T>(offset)?;
+        ptr.write(value);
+        Ok(&*ptr)
+    }
+
+    /// Returns a reference to a `T` that some process already placed at `offset`.
+    ///
+    /// # Safety
+    ///
+    /// The bytes at `offset` must hold a valid, initialized `T`.
+    pub unsafe fn attach<T: ShmSafe>(&self, offset: usize) -> Result<&T> {
+        Ok(&*self.slot::<T>(offset)?)
+    }
+
+    pub(crate) fn slot<T>(&self, offset: usize) -> Result<*mut T> {
+        let end = offset.checked_add(mem::size_of::<T>());
+        if end.map_or(true, |end| end > self.size) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "out of shm bounds").into());
+        }
+        let ptr = unsafe { self.addr.add(offset) };
+        if ptr as usize % mem::align_of::<T>() != 0 {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidInput, "misaligned shm offset").into(),
+            );
+        }
+        Ok(ptr as *mut T)
+    }
+
+    /// [`slot`](Self::slot) without the checks, for the fixed layouts whose
+    /// offsets were validated against the mapping's size when it was opened.
+    ///
+    /// # Safety
+    ///
+    /// `offset..offset + size_of::<T>()` must lie inside the mapping, and
+    /// `offset` must be a multiple of `align_of::<T>()`.
+    pub(crate) unsafe fn slot_unchecked<T>(&self, offset: usize) -> *mut T {
+        debug_assert!(self.slot::<T>(offset).is_ok());
+        self.addr.add(offset) as *mut T
+    }
+
     pub fn as_slice(&self) -> &[u8] {
         unsafe { slice::from_raw_parts(self.addr, self.size) }
     }
@@ -85,6 +196,10 @@ impl Shm {
         self.size
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -92,20 +207,43 @@ impl Shm {
     pub fn owner(&self) -> bool {
         self.owner
     }
-}
 
-impl Drop for Shm {
-    fn drop(&mut self) {
+    /// Unmaps the memory and, if owned, unlinks the name, reporting the
+    /// errors that dropping would only log.
+    pub fn close(mut self) -> Result<()> {
+        self.release()
+    }
+
+    pub(crate) fn release(&mut self) -> Result<()> {
+        let mut result = Ok(());
         unsafe {
-            if libc::munmap(self.addr as _, self.size as _) == -1 {
-                panic_errno!("munmap");
+            if !self.addr.is_null() {
+                if libc::munmap(self.addr as _, self.size as _) == -1 {
+                    result = Err(Error::errno(libc_errno(), "munmap", Some(&self.name)));
+                }
+                self.addr = ptr::null_mut();
             }
             if self.owner {
-                let c_string = CString::new(&*self.name).expect("CString::new");
-                if libc::shm_unlink(c_string.as_ptr()) == -1 {
-                    panic_errno!("shm_unlink");
+                self.owner = false;
+                let c_name = CString::new(&*self.name)?;
+                // 其他进程已经 unlink 过也算成功
+                if libc::shm_unlink(c_name.as_ptr()) == -1 && libc_errno() != libc::ENOENT {
+                    result = result.and(Err(Error::errno(
+                        libc_errno(),
+                        "shm_unlink",
+                        Some(&self.name),
+                    )));
                 }
             }
         }
+        result
+    }
+}
+
+impl Drop for Shm {
+    fn drop(&mut self) {
+        if let Err(err) = self.release() {
+            error!("{}", err);
+        }
     }
 }
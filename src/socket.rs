@@ -0,0 +1,97 @@
+use crate::{fd, Result};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+
+/// One end of a connected `SOCK_SEQPACKET` Unix socket pair: reliable and
+/// ordered like a stream, but every `send` arrives as one `recv`.
+#[derive(Debug)]
+pub struct SeqPacket(OwnedFd);
+
+impl SeqPacket {
+    /// Creates a connected pair, both ends close-on-exec. Pass one end to a
+    /// child with `fork` or [`ChildBuilder`](crate::process::ChildBuilder).
+    pub fn pair() -> Result<(SeqPacket, SeqPacket)> {
+        unsafe {
+            let mut fds: [libc::c_int; 2] = [0, 0];
+            let ret = libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
+            );
+            if ret == -1 {
+                return_errno!("socketpair");
+            }
+            Ok((
+                SeqPacket(OwnedFd::from_raw_fd(fds[0])),
+                SeqPacket(OwnedFd::from_raw_fd(fds[1])),
+            ))
+        }
+    }
+
+    /// Sends `packet` as one packet. Fails with `EPIPE` instead of raising
+    /// `SIGPIPE` once the peer is gone.
+    pub fn send(&self, packet: &[u8]) -> Result<usize> {
+        unsafe {
+            let n = libc::send(
+                self.0.as_raw_fd(),
+                packet.as_ptr() as _,
+                packet.len(),
+                libc::MSG_NOSIGNAL,
+            );
+            if n == -1 {
+                return_errno!("send");
+            }
+            Ok(n as _)
+        }
+    }
+
+    /// Receives the next packet into `buf`, discarding the bytes that don't
+    /// fit. Returns 0 once the peer has closed its end.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        unsafe {
+            let n = libc::recv(self.0.as_raw_fd(), buf.as_mut_ptr() as _, buf.len(), 0);
+            if n == -1 {
+                return_errno!("recv");
+            }
+            Ok(n as _)
+        }
+    }
+
+    pub fn try_clone(&self) -> Result<SeqPacket> {
+        Ok(SeqPacket(fd::try_clone(self.as_fd())?))
+    }
+
+    pub fn close(self) -> Result<()> {
+        fd::close(self.0)
+    }
+}
+
+impl AsFd for SeqPacket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl AsRawFd for SeqPacket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for SeqPacket {
+    fn into_raw_fd(self) -> RawFd {
+        self.0.into_raw_fd()
+    }
+}
+
+impl From<SeqPacket> for OwnedFd {
+    fn from(socket: SeqPacket) -> OwnedFd {
+        socket.0
+    }
+}
+
+impl From<OwnedFd> for SeqPacket {
+    fn from(fd: OwnedFd) -> SeqPacket {
+        SeqPacket(fd)
+    }
+}
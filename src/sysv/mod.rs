@@ -0,0 +1,44 @@
+//! System V IPC: message queues, semaphore sets and shared memory segments.
+//!
+//! Objects are identified by a [`Key`] and live until removed with
+//! `IPC_RMID`, independently of the processes using them.
+
+use crate::Result;
+use std::ffi::CString;
+
+mod msg;
+mod sem;
+mod shm;
+
+pub use msg::{MessageQueue, MessageType};
+pub use sem::{SemOp, SemaphoreSet, SetPermit};
+pub use shm::SharedMemory;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key(libc::key_t);
+
+impl Key {
+    /// Always creates a new object. Its id is only known to the creator and
+    /// to children forked after creation.
+    pub const PRIVATE: Key = Key(libc::IPC_PRIVATE);
+
+    pub const fn new(key: libc::key_t) -> Key {
+        Key(key)
+    }
+
+    /// Derives a key from an existing file and a non-zero project id.
+    pub fn ftok(path: &str, proj_id: u8) -> Result<Key> {
+        unsafe {
+            let c_path = CString::new(path)?;
+            let key = libc::ftok(c_path.as_ptr(), proj_id as _);
+            if key == -1 {
+                return_errno!("ftok", path);
+            }
+            Ok(Key(key))
+        }
+    }
+
+    pub fn raw(&self) -> libc::key_t {
+        self.0
+    }
+}
@@ -0,0 +1,167 @@
+use super::Key;
+use crate::errors::libc_errno;
+use crate::flags::{IpcFlags, Mode};
+use crate::Result;
+use std::{io, mem, ptr};
+
+/// Which message `msgrcv` picks from the queue. Types are positive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    /// The first message of any type.
+    Any,
+    /// The first message of exactly this type.
+    Exact(i64),
+    /// The first message of any other type (`MSG_EXCEPT`).
+    Except(i64),
+    /// The first message with the lowest type not greater than this one.
+    AtMost(i64),
+}
+
+impl MessageType {
+    fn as_raw(&self) -> Result<(libc::c_long, libc::c_int)> {
+        match *self {
+            MessageType::Any => Ok((0, 0)),
+            MessageType::Exact(t) if t > 0 => Ok((t as _, 0)),
+            MessageType::Except(t) if t > 0 => Ok((t as _, libc::MSG_EXCEPT)),
+            // 正数取负不会溢出
+            MessageType::AtMost(t) if t > 0 => Ok((-t as _, 0)),
+            _ => Err(
+                io::Error::new(io::ErrorKind::InvalidInput, "message type must be positive").into(),
+            ),
+        }
+    }
+}
+
+const TYPE_SIZE: usize = mem::size_of::<libc::c_long>();
+
+/// A System V message queue (`msgget`/`msgsnd`/`msgrcv`).
+#[derive(Debug)]
+pub struct MessageQueue {
+    id: libc::c_int,
+    // msgsnd/msgrcv 要求 mtype 与正文连续存放
+    buf: Vec<u8>,
+}
+
+impl MessageQueue {
+    pub fn open(key: Key, flags: IpcFlags, mode: Mode) -> Result<MessageQueue> {
+        unsafe {
+            let id = libc::msgget(key.raw(), flags.bits() | mode.bits() as libc::c_int);
+            if id == -1 {
+                return_errno!("msgget");
+            }
+            Ok(MessageQueue::from_id(id))
+        }
+    }
+
+    /// Wraps a queue id obtained elsewhere, e.g. before `fork`.
+    pub fn from_id(id: libc::c_int) -> MessageQueue {
+        MessageQueue {
+            id,
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn id(&self) -> libc::c_int {
+        self.id
+    }
+
+    /// Sends `data` as a message of type `mtype`, which must be positive.
+    pub fn send(&mut self, mtype: i64, data: &[u8]) -> Result<()> {
+        self.send_raw(mtype, data, 0)?;
+        Ok(())
+    }
+
+    /// Returns `false` instead of blocking when the queue is full.
+    pub fn try_send(&mut self, mtype: i64, data: &[u8]) -> Result<bool> {
+        self.send_raw(mtype, data, libc::IPC_NOWAIT)
+    }
+
+    /// Receives one message into `buf`, returning its type and length.
+    /// Messages longer than `buf` fail with `E2BIG` and stay queued.
+    pub fn recv(&mut self, selector: MessageType, buf: &mut [u8]) -> Result<(i64, usize)> {
+        match self.recv_raw(selector, buf, 0)? {
+            Some(msg) => Ok(msg),
+            None => unreachable!("msgrcv without IPC_NOWAIT"),
+        }
+    }
+
+    /// Returns `None` instead of blocking when no message matches.
+    pub fn try_recv(
+        &mut self,
+        selector: MessageType,
+        buf: &mut [u8],
+    ) -> Result<Option<(i64, usize)>> {
+        self.recv_raw(selector, buf, libc::IPC_NOWAIT)
+    }
+
+    pub fn message_count(&self) -> Result<usize> {
+        unsafe {
+            let mut ds: libc::msqid_ds = mem::zeroed();
+            if libc::msgctl(self.id, libc::IPC_STAT, &mut ds) == -1 {
+                return_errno!("msgctl");
+            }
+            Ok(ds.msg_qnum as _)
+        }
+    }
+
+    /// Removes the queue from the system (`IPC_RMID`). Processes blocked on
+    /// it are woken with `EIDRM`.
+    pub fn remove(self) -> Result<()> {
+        unsafe {
+            if libc::msgctl(self.id, libc::IPC_RMID, ptr::null_mut()) == -1 {
+                return_errno!("msgctl");
+            }
+            Ok(())
+        }
+    }
+
+    fn send_raw(&mut self, mtype: i64, data: &[u8], flags: libc::c_int) -> Result<bool> {
+        self.buf.clear();
+        self.buf
+            .extend_from_slice(&(mtype as libc::c_long).to_ne_bytes());
+        self.buf.extend_from_slice(data);
+        unsafe {
+            while libc::msgsnd(self.id, self.buf.as_ptr() as _, data.len(), flags) == -1 {
+                match libc_errno() {
+                    libc::EINTR => continue,
+                    libc::EAGAIN if flags & libc::IPC_NOWAIT != 0 => return Ok(false),
+                    _ => return_errno!("msgsnd"),
+                }
+            }
+            Ok(true)
+        }
+    }
+
+    fn recv_raw(
+        &mut self,
+        selector: MessageType,
+        buf: &mut [u8],
+        flags: libc::c_int,
+    ) -> Result<Option<(i64, usize)>> {
+        let (msgtyp, extra) = selector.as_raw()?;
+        self.buf.resize(TYPE_SIZE + buf.len(), 0);
+        unsafe {
+            let n = loop {
+                let n = libc::msgrcv(
+                    self.id,
+                    self.buf.as_mut_ptr() as _,
+                    buf.len(),
+                    msgtyp,
+                    flags | extra,
+                );
+                if n != -1 {
+                    break n as usize;
+                }
+                match libc_errno() {
+                    libc::EINTR => continue,
+                    libc::ENOMSG if flags & libc::IPC_NOWAIT != 0 => return Ok(None),
+                    _ => return_errno!("msgrcv"),
+                }
+            };
+            let mut mtype = [0u8; TYPE_SIZE];
+            mtype.copy_from_slice(&self.buf[..TYPE_SIZE]);
+            buf[..n].copy_from_slice(&self.buf[TYPE_SIZE..TYPE_SIZE + n]);
+            Ok(Some((libc::c_long::from_ne_bytes(mtype) as _, n)))
+        }
+    }
+}
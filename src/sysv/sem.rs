@@ -0,0 +1,240 @@
+use super::Key;
+use crate::errors::libc_errno;
+use crate::flags::{IpcFlags, Mode};
+use crate::Result;
+use log::error;
+use std::time::Duration;
+use std::{io, ptr};
+
+// libc 未导出以下常量
+const SEM_UNDO: libc::c_short = 0x1000;
+const GETVAL: libc::c_int = 12;
+const GETALL: libc::c_int = 13;
+const SETVAL: libc::c_int = 16;
+const SETALL: libc::c_int = 17;
+
+/// One operation of an atomic [`SemaphoreSet::op`] call.
+#[derive(Debug, Clone, Copy)]
+#[repr(transparent)]
+pub struct SemOp(libc::sembuf);
+
+impl SemOp {
+    /// Adds `delta` to semaphore `index`. A negative delta blocks until the
+    /// value is large enough, zero blocks until the value is zero.
+    pub fn new(index: u16, delta: i16) -> SemOp {
+        SemOp(libc::sembuf {
+            sem_num: index,
+            sem_op: delta,
+            sem_flg: 0,
+        })
+    }
+
+    pub fn wait(index: u16) -> SemOp {
+        SemOp::new(index, -1)
+    }
+
+    pub fn post(index: u16) -> SemOp {
+        SemOp::new(index, 1)
+    }
+
+    pub fn wait_zero(index: u16) -> SemOp {
+        SemOp::new(index, 0)
+    }
+
+    /// Has the kernel revert this operation when the process exits
+    /// (`SEM_UNDO`), so a crashed holder doesn't leak the semaphore.
+    pub fn undo(mut self) -> SemOp {
+        self.0.sem_flg |= SEM_UNDO;
+        self
+    }
+}
+
+/// A System V semaphore set (`semget`/`semop`).
+#[derive(Debug)]
+pub struct SemaphoreSet {
+    id: libc::c_int,
+    count: usize,
+}
+
+impl SemaphoreSet {
+    /// New semaphores start at zero.
+    pub fn open(key: Key, count: usize, flags: IpcFlags, mode: Mode) -> Result<SemaphoreSet> {
+        unsafe {
+            let id = libc::semget(
+                key.raw(),
+                count as _,
+                flags.bits() | mode.bits() as libc::c_int,
+            );
+            if id == -1 {
+                return_errno!("semget");
+            }
+            Ok(SemaphoreSet { id, count })
+        }
+    }
+
+    /// Wraps a set id obtained elsewhere, e.g. before `fork`.
+    pub fn from_id(id: libc::c_int, count: usize) -> SemaphoreSet {
+        SemaphoreSet { id, count }
+    }
+
+    pub fn id(&self) -> libc::c_int {
+        self.id
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn value(&self, index: u16) -> Result<usize> {
+        unsafe {
+            let val = libc::semctl(self.id, index as _, GETVAL);
+            if val == -1 {
+                return_errno!("semctl");
+            }
+            Ok(val as _)
+        }
+    }
+
+    pub fn set_value(&self, index: u16, value: usize) -> Result<()> {
+        unsafe {
+            if libc::semctl(self.id, index as _, SETVAL, value as libc::c_int) == -1 {
+                return_errno!("semctl");
+            }
+            Ok(())
+        }
+    }
+
+    pub fn values(&self) -> Result<Vec<u16>> {
+        let mut values = vec![0u16; self.count];
+        unsafe {
+            if libc::semctl(self.id, 0, GETALL, values.as_mut_ptr()) == -1 {
+                return_errno!("semctl");
+            }
+        }
+        Ok(values)
+    }
+
+    pub fn set_values(&self, values: &[u16]) -> Result<()> {
+        if values.len() != self.count {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidInput, "one value per semaphore").into(),
+            );
+        }
+        unsafe {
+            if libc::semctl(self.id, 0, SETALL, values.as_ptr()) == -1 {
+                return_errno!("semctl");
+            }
+            Ok(())
+        }
+    }
+
+    /// Applies all `ops` atomically: either every one of them proceeds or
+    /// the call blocks without changing any semaphore.
+    pub fn op(&self, ops: &[SemOp]) -> Result<()> {
+        self.semtimedop(ops, None, 0)?;
+        Ok(())
+    }
+
+    /// Returns `false` instead of blocking (`IPC_NOWAIT`).
+    pub fn try_op(&self, ops: &[SemOp]) -> Result<bool> {
+        self.semtimedop(ops, None, libc::IPC_NOWAIT as _)
+    }
+
+    /// Returns `false` if the operations could not proceed within `timeout`.
+    pub fn op_timeout(&self, ops: &[SemOp], timeout: Duration) -> Result<bool> {
+        self.semtimedop(ops, Some(timeout), 0)
+    }
+
+    /// Takes `n` permits from semaphore `index` in a single `semop`, so
+    /// callers racing for the last permits never each hold a part of them.
+    pub fn acquire_many(&self, index: u16, n: u16) -> Result<SetPermit<'_>> {
+        let permits = i16::try_from(n)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many permits"))?;
+        self.op(&[SemOp::new(index, -permits)])?;
+        Ok(SetPermit {
+            set: self,
+            index,
+            permits,
+        })
+    }
+
+    /// Removes the set from the system (`IPC_RMID`). Processes blocked on it
+    /// are woken with `EIDRM`.
+    pub fn remove(self) -> Result<()> {
+        unsafe {
+            if libc::semctl(self.id, 0, libc::IPC_RMID) == -1 {
+                return_errno!("semctl");
+            }
+            Ok(())
+        }
+    }
+
+    fn semtimedop(
+        &self,
+        ops: &[SemOp],
+        timeout: Option<Duration>,
+        flags: libc::c_short,
+    ) -> Result<bool> {
+        let mut ops = ops.to_vec();
+        for op in ops.iter_mut() {
+            op.0.sem_flg |= flags;
+        }
+        let ts = timeout.map(|t| libc::timespec {
+            tv_sec: t.as_secs() as _,
+            tv_nsec: t.subsec_nanos() as _,
+        });
+        let ts = ts.as_ref().map_or(ptr::null(), |ts| ts as *const _);
+        unsafe {
+            while libc::syscall(
+                libc::SYS_semtimedop,
+                self.id,
+                ops.as_ptr() as *const libc::sembuf,
+                ops.len(),
+                ts,
+            ) == -1
+            {
+                match libc_errno() {
+                    libc::EINTR => continue,
+                    libc::EAGAIN => return Ok(false),
+                    _ => return_errno!("semtimedop"),
+                }
+            }
+            Ok(true)
+        }
+    }
+}
+
+/// Permits taken with [`SemaphoreSet::acquire_many`], posted back when
+/// dropped.
+#[derive(Debug)]
+pub struct SetPermit<'a> {
+    set: &'a SemaphoreSet,
+    index: u16,
+    permits: i16,
+}
+
+impl SetPermit<'_> {
+    pub fn permits(&self) -> usize {
+        self.permits as _
+    }
+
+    /// Keeps the permits taken: nothing is posted back.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for SetPermit<'_> {
+    fn drop(&mut self) {
+        if self.permits == 0 {
+            return;
+        }
+        if let Err(err) = self.set.op(&[SemOp::new(self.index, self.permits)]) {
+            error!("SetPermit: {}", err);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use super::Key;
+use crate::flags::{IpcFlags, Mode};
+use crate::Result;
+use log::error;
+use std::{mem, ptr, slice};
+
+/// An attached System V shared memory segment (`shmget`/`shmat`).
+///
+/// Dropping it detaches the segment; the segment itself lives on until
+/// [`remove`](Self::remove) is called and every process has detached.
+#[derive(Debug)]
+pub struct SharedMemory {
+    id: libc::c_int,
+    addr: *mut u8,
+    size: usize,
+}
+
+unsafe impl Send for SharedMemory {}
+unsafe impl Sync for SharedMemory {}
+
+impl SharedMemory {
+    pub fn open(key: Key, size: usize, flags: IpcFlags, mode: Mode) -> Result<SharedMemory> {
+        unsafe {
+            let id = libc::shmget(key.raw(), size, flags.bits() | mode.bits() as libc::c_int);
+            if id == -1 {
+                return_errno!("shmget");
+            }
+            Self::attach(id)
+        }
+    }
+
+    /// Attaches a segment by id, e.g. one created before `fork`.
+    pub fn attach(id: libc::c_int) -> Result<SharedMemory> {
+        unsafe {
+            let mut ds: libc::shmid_ds = mem::zeroed();
+            if libc::shmctl(id, libc::IPC_STAT, &mut ds) == -1 {
+                return_errno!("shmctl");
+            }
+            let addr = libc::shmat(id, ptr::null(), 0);
+            if addr as isize == -1 {
+                return_errno!("shmat");
+            }
+            Ok(SharedMemory {
+                id,
+                addr: addr as _,
+                size: ds.shm_segsz as _,
+            })
+        }
+    }
+
+    pub fn id(&self) -> libc::c_int {
+        self.id
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.addr, self.size) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.addr, self.size) }
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.addr
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.addr
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Detaches the segment, reporting the error that dropping would only log.
+    pub fn close(mut self) -> Result<()> {
+        self.release()
+    }
+
+    fn release(&mut self) -> Result<()> {
+        if self.addr.is_null() {
+            return Ok(());
+        }
+        let addr = mem::replace(&mut self.addr, ptr::null_mut());
+        unsafe {
+            if libc::shmdt(addr as _) == -1 {
+                return_errno!("shmdt");
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks the segment for removal (`IPC_RMID`). It is destroyed once the
+    /// last process detaches.
+    pub fn remove(&self) -> Result<()> {
+        unsafe {
+            if libc::shmctl(self.id, libc::IPC_RMID, ptr::null_mut()) == -1 {
+                return_errno!("shmctl");
+            }
+            Ok(())
+        }
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        if let Err(err) = self.release() {
+            error!("{}", err);
+        }
+    }
+}
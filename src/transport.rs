@@ -0,0 +1,334 @@
+//! One message-passing interface over every IPC mechanism in the crate.
+//!
+//! Message-oriented transports (message queues, packet pipes, seqpacket and
+//! datagram sockets) carry each message as one kernel message. Byte streams
+//! (pipes, FIFOs, the shm ring) implement [`ByteSender`]/[`ByteReceiver`] and
+//! get messages by framing: a little-endian `u32` length, then the payload.
+//! Framing assumes one writer per stream.
+
+use crate::fd::FdExt;
+use crate::flags::{Mode, OpenFlags, PipeFlags};
+#[cfg(not(target_os = "android"))]
+use crate::mq::{MQAttribute, MessageQueue};
+use crate::pipe::{
+    self, FifoReader, FifoWriter, PacketReader, PacketWriter, PipeReader, PipeWriter,
+};
+#[cfg(not(target_os = "android"))]
+use crate::ring;
+use crate::socket::SeqPacket;
+use crate::{Error, Result};
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd};
+use std::os::unix::net::UnixDatagram;
+use std::{cmp, mem};
+
+/// Sends whole messages: each `send` is received by exactly one `recv`.
+pub trait MessageSender {
+    fn send(&mut self, msg: &[u8]) -> Result<()>;
+
+    /// The largest message the transport accepts, if it has a fixed limit.
+    fn max_message_size(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Receives whole messages sent by a [`MessageSender`].
+pub trait MessageReceiver {
+    /// Receives the next message into `buf` and returns its length, or
+    /// `None` once the sending side is gone. A message longer than `buf`
+    /// fails with [`Error::MessageTooLarge`]; `buf` then holds its first
+    /// bytes and the rest is discarded, so the next call moves on.
+    fn recv(&mut self, buf: &mut [u8]) -> Result<Option<usize>>;
+
+    /// The largest message the transport delivers, if it has a fixed limit.
+    fn max_message_size(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// The writing side of a transport that carries an unframed byte stream.
+pub trait ByteSender: Write {}
+
+/// The reading side of a transport that carries an unframed byte stream.
+pub trait ByteReceiver: Read {}
+
+impl ByteSender for PipeWriter {}
+impl ByteReceiver for PipeReader {}
+impl ByteSender for FifoWriter {}
+impl ByteReceiver for FifoReader {}
+impl ByteSender for pipe::Fifo {}
+impl ByteReceiver for pipe::Fifo {}
+#[cfg(not(target_os = "android"))]
+impl ByteSender for ring::Buffer {}
+#[cfg(not(target_os = "android"))]
+impl ByteReceiver for ring::Buffer {}
+
+impl<T: ByteSender> MessageSender for T {
+    fn send(&mut self, msg: &[u8]) -> Result<()> {
+        let len = u32::try_from(msg.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "message too long"))?;
+        self.write_all(&len.to_le_bytes())?;
+        self.write_all(msg)?;
+        Ok(())
+    }
+
+    fn max_message_size(&self) -> Option<usize> {
+        Some(u32::MAX as usize)
+    }
+}
+
+impl<T: ByteReceiver> MessageReceiver for T {
+    fn recv(&mut self, buf: &mut [u8]) -> Result<Option<usize>> {
+        let mut header = [0u8; mem::size_of::<u32>()];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        let len = u32::from_le_bytes(header) as usize;
+        let kept = cmp::min(len, buf.len());
+        self.read_exact(&mut buf[..kept])?;
+        let rest = (len - kept) as u64;
+        if io::copy(&mut self.take(rest), &mut io::sink())? != rest {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        fit(len, buf).map(Some)
+    }
+}
+
+fn fit(len: usize, buf: &[u8]) -> Result<usize> {
+    if len > buf.len() {
+        return Err(Error::MessageTooLarge { max: buf.len() });
+    }
+    Ok(len)
+}
+
+#[cfg(not(target_os = "android"))]
+impl MessageSender for MessageQueue {
+    fn send(&mut self, msg: &[u8]) -> Result<()> {
+        // mq_send 要么整条发出要么失败；空消息也要发，不能用 write_all
+        let sent = self.write(msg)?;
+        debug_assert_eq!(sent, msg.len());
+        Ok(())
+    }
+
+    fn max_message_size(&self) -> Option<usize> {
+        self.attributes()
+            .ok()
+            .map(|attr| attr.message_size() as usize)
+    }
+}
+
+#[cfg(not(target_os = "android"))]
+impl MessageReceiver for MessageQueue {
+    /// `buf` must hold at least the queue's message size, or `mq_receive`
+    /// fails with `EMSGSIZE`.
+    fn recv(&mut self, buf: &mut [u8]) -> Result<Option<usize>> {
+        Ok(Some(self.read(buf)?))
+    }
+
+    fn max_message_size(&self) -> Option<usize> {
+        MessageSender::max_message_size(self)
+    }
+}
+
+impl MessageSender for PacketWriter {
+    /// Empty messages are rejected: a packet pipe can't carry them.
+    fn send(&mut self, msg: &[u8]) -> Result<()> {
+        check_not_empty(msg)?;
+        PacketWriter::send(self, msg)
+    }
+
+    fn max_message_size(&self) -> Option<usize> {
+        Some(pipe::PIPE_BUF)
+    }
+}
+
+impl MessageReceiver for PacketReader {
+    fn recv(&mut self, buf: &mut [u8]) -> Result<Option<usize>> {
+        if buf.len() >= pipe::PIPE_BUF {
+            return match PacketReader::recv(self, buf)? {
+                0 => Ok(None),
+                n => Ok(Some(n)),
+            };
+        }
+        // 包的长度读之前无从得知，先整包读进足够大的缓冲区
+        let mut packet = [0u8; pipe::PIPE_BUF];
+        let n = PacketReader::recv(self, &mut packet)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        let kept = cmp::min(n, buf.len());
+        buf[..kept].copy_from_slice(&packet[..kept]);
+        fit(n, buf).map(Some)
+    }
+
+    fn max_message_size(&self) -> Option<usize> {
+        Some(pipe::PIPE_BUF)
+    }
+}
+
+impl MessageSender for SeqPacket {
+    /// Empty messages are rejected: the receiver would take one for the
+    /// peer closing.
+    fn send(&mut self, msg: &[u8]) -> Result<()> {
+        check_not_empty(msg)?;
+        SeqPacket::send(self, msg)?;
+        Ok(())
+    }
+}
+
+impl MessageReceiver for SeqPacket {
+    fn recv(&mut self, buf: &mut [u8]) -> Result<Option<usize>> {
+        match recv_packet(self.as_fd(), buf)? {
+            0 => Ok(None),
+            n => Ok(Some(n)),
+        }
+    }
+}
+
+/// Receives one packet like `recv`, but returns its full length even when
+/// only part of it fit in `buf`.
+fn recv_packet(fd: BorrowedFd<'_>, buf: &mut [u8]) -> Result<usize> {
+    unsafe {
+        // MSG_TRUNC 让 recv 返回整个包的长度，而不是拷进 buf 的字节数
+        let n = libc::recv(
+            fd.as_raw_fd(),
+            buf.as_mut_ptr() as _,
+            buf.len(),
+            libc::MSG_TRUNC,
+        );
+        if n == -1 {
+            return_errno!("recv");
+        }
+        fit(n as usize, buf)
+    }
+}
+
+fn check_not_empty(msg: &[u8]) -> Result<()> {
+    if msg.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty message").into());
+    }
+    Ok(())
+}
+
+impl MessageSender for UnixDatagram {
+    fn send(&mut self, msg: &[u8]) -> Result<()> {
+        UnixDatagram::send(self, msg)?;
+        Ok(())
+    }
+}
+
+impl MessageReceiver for UnixDatagram {
+    /// Datagrams have no end of stream, so this never returns `None`.
+    fn recv(&mut self, buf: &mut [u8]) -> Result<Option<usize>> {
+        Ok(Some(recv_packet(self.as_fd(), buf)?))
+    }
+}
+
+pub type BoxedSender = Box<dyn MessageSender + Send>;
+pub type BoxedReceiver = Box<dyn MessageReceiver + Send>;
+
+/// Picks the IPC mechanism behind a connected sender/receiver pair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+    Pipe,
+    /// A pipe in packet mode; messages are limited to `PIPE_BUF` bytes.
+    PacketPipe,
+    /// A FIFO created at this path, unlinked once the receiver is dropped.
+    Fifo(String),
+    /// A POSIX message queue, unlinked as soon as both ends are open.
+    #[cfg(not(target_os = "android"))]
+    MessageQueue {
+        name: String,
+        capacity: usize,
+        message_size: usize,
+    },
+    /// The shared-memory ring buffer under `name`, holding `size` bytes.
+    /// Needs the `ring-futex` feature, or the receiver would busy-spin. The
+    /// ring has no disconnect signal: `recv` never returns `None`, so the
+    /// peers must agree on the last message themselves.
+    #[cfg(not(target_os = "android"))]
+    Ring {
+        name: String,
+        size: u32,
+    },
+    SeqPacket,
+    Datagram,
+}
+
+impl Transport {
+    /// Creates a connected pair. Create it before `fork` and keep one end
+    /// on each side; every end is close-on-exec.
+    pub fn pair(&self) -> Result<(BoxedSender, BoxedReceiver)> {
+        match self {
+            Transport::Pipe => {
+                let (reader, writer) = pipe::pipe2(PipeFlags::CLOEXEC)?;
+                Ok((Box::new(writer), Box::new(reader)))
+            }
+            Transport::PacketPipe => {
+                let (reader, writer) = pipe::packet_pipe(PipeFlags::CLOEXEC)?;
+                Ok((Box::new(writer), Box::new(reader)))
+            }
+            Transport::Fifo(path) => {
+                // 两端分开打开，写端关闭后读端才能读到 EOF；读端先以非阻塞打开，不必等写端
+                let reader = pipe::Fifo::create(
+                    path,
+                    pipe::Access::Read,
+                    OpenFlags::NONBLOCK | OpenFlags::CLOEXEC,
+                    Mode::from_bits_truncate(0o600),
+                )?;
+                let writer = FifoWriter::open(path, OpenFlags::CLOEXEC)?;
+                reader.set_nonblocking(false)?;
+                Ok((Box::new(writer), Box::new(reader)))
+            }
+            #[cfg(not(target_os = "android"))]
+            Transport::MessageQueue {
+                name,
+                capacity,
+                message_size,
+            } => {
+                let mut attr = MQAttribute::new();
+                attr.set_max_message_count(*capacity as _)
+                    .set_message_size(*message_size as _);
+                let perm = Mode::from_bits_truncate(0o600);
+                let receiver = MessageQueue::open_with_attributes(
+                    name,
+                    OpenFlags::CREAT | OpenFlags::EXCL | OpenFlags::RDONLY,
+                    perm,
+                    &attr,
+                )?;
+                let sender = MessageQueue::open(name, OpenFlags::WRONLY, perm);
+                // 两端都已打开，名字不再需要
+                MessageQueue::unlink(name)?;
+                Ok((Box::new(sender?), Box::new(receiver)))
+            }
+            #[cfg(not(target_os = "android"))]
+            Transport::Ring { name, size } => {
+                if !cfg!(feature = "ring-futex") {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "ring transport needs the ring-futex feature",
+                    )
+                    .into());
+                }
+                let receiver = ring::Buffer::new(name, true, *size)?;
+                let sender = ring::Buffer::new(name, false, *size)?;
+                Ok((Box::new(sender), Box::new(receiver)))
+            }
+            Transport::SeqPacket => {
+                let (sender, receiver) = SeqPacket::pair()?;
+                Ok((Box::new(sender), Box::new(receiver)))
+            }
+            Transport::Datagram => {
+                let (sender, receiver) = UnixDatagram::pair()?;
+                Ok((Box::new(sender), Box::new(receiver)))
+            }
+        }
+    }
+}